@@ -0,0 +1,102 @@
+// compares EntityHeap against the HashMap it replaced,
+//   on the inserts, removals and per-frame iteration that clusters cause
+// run with `cargo run --release --example heap_bench`
+
+extern crate charm_internal;
+
+use std::collections;
+use std::time;
+
+use charm_internal::entity_heap;
+use charm_internal::physics;
+use charm_internal::units;
+
+const ENTITIES: usize = 100_000;
+const FRAMES: usize = 60;
+
+struct Mote {
+    body: physics::Body,
+}
+
+impl entity_heap::AsEntity for Mote {
+    const KIND: &'static str = "Mote";
+
+    fn body(&self) -> Option<&physics::Body> {
+        Some(&self.body)
+    }
+}
+
+fn mote(i: usize) -> Mote {
+    let position = units::Vec2 {
+        x: i as units::Scalar * units::DOT,
+        y: 0,
+    };
+    let velocity = units::Vec2 { x: 0, y: 100 };
+    let body = physics::Body::new(position, velocity, 0);
+    Mote { body }
+}
+
+// something for the iteration to compute, so that it isn't optimised out
+fn sum_positions<'a, I>(entities: I) -> units::Scalar
+    where I: Iterator<Item = &'a entity_heap::Entity>
+{
+    entities
+        .filter_map(|ent| ent.body())
+        .map(|body| body.position(units::SEC).y)
+        .fold(0, |sum, y| sum.wrapping_add(y))
+}
+
+fn run_heap() -> (time::Duration, units::Scalar) {
+    let start = time::Instant::now();
+    let mut space = entity_heap::EntityHeap::new();
+
+    let mut uids = Vec::with_capacity(ENTITIES);
+    for i in 0..ENTITIES {
+        uids.push(entity_heap::new_entity(&mut space, mote(i)));
+    }
+    // every other entity is removed and replaced, like smoke clearing
+    for (i, &uid) in uids.iter().enumerate().step_by(2) {
+        space.remove(uid).expect("each mote is removed once");
+        entity_heap::new_entity(&mut space, mote(i));
+    }
+    let mut total = 0;
+    for _ in 0..FRAMES {
+        let sum = sum_positions(space.iter().map(|(_, ent)| ent));
+        total = sum.wrapping_add(total);
+    }
+
+    (start.elapsed(), total)
+}
+
+fn run_map() -> (time::Duration, units::Scalar) {
+    let start = time::Instant::now();
+    let mut space = collections::HashMap::new();
+
+    let mut next_uid: entity_heap::UID = 0;
+    for i in 0..ENTITIES {
+        space.insert(next_uid, entity_heap::Entity::new(mote(i)));
+        next_uid += 1;
+    }
+    for i in (0..ENTITIES).step_by(2) {
+        space.remove(&(i as entity_heap::UID));
+        space.insert(next_uid, entity_heap::Entity::new(mote(i)));
+        next_uid += 1;
+    }
+    let mut total = 0;
+    for _ in 0..FRAMES {
+        let sum = sum_positions(space.values());
+        total = sum.wrapping_add(total);
+    }
+
+    (start.elapsed(), total)
+}
+
+fn main() {
+    let (heap, heap_total) = run_heap();
+    let (map, map_total) = run_map();
+    assert_eq!(heap_total, map_total);
+
+    println!("{} entities, {} frames of iteration", ENTITIES, FRAMES);
+    println!("  slot map: {:?}", heap);
+    println!("  hash map: {:?}", map);
+}
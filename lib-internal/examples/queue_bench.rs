@@ -0,0 +1,102 @@
+// compares the two EventQueue backends on a cluster spell sized load
+// run with `cargo run --release --example queue_bench`
+
+extern crate charm_internal;
+
+use std::time;
+
+use charm_internal::entity_heap;
+use charm_internal::event_queue;
+use charm_internal::units;
+
+const EVENTS: usize = 100_000;
+const FRAME: units::Duration = units::SEC / 60;
+
+struct Nop;
+
+impl event_queue::Event for Nop {
+    fn invoke(
+        self: Self,
+        _space: &mut entity_heap::EntityHeap,
+        _time: &mut event_queue::EventQueue
+    ) -> Result<(), event_queue::EventError> {
+        Ok(())
+    }
+}
+
+// lands after a while, and sometimes splits into more bolts
+// roughly what a deeply nested cluster grenade does to the queue
+struct Split {
+    depth: u32,
+    seed: u64,
+}
+
+impl event_queue::Event for Split {
+    fn invoke(
+        self: Self,
+        _space: &mut entity_heap::EntityHeap,
+        time: &mut event_queue::EventQueue
+    ) -> Result<(), event_queue::EventError> {
+        if self.depth > 0 {
+            let mut seed = self.seed;
+            for _ in 0..4 {
+                seed = next_random(seed);
+                let delay = (seed % units::SEC as u64) as units::Duration;
+                let child = Split { depth: self.depth - 1, seed };
+                time.enqueue(child, delay)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn next_random(seed: u64) -> u64 {
+    seed.wrapping_mul(6364136223846793005)
+        .wrapping_add(1442695040888963407)
+}
+
+fn run_flat(mut time: event_queue::EventQueue) -> time::Duration {
+    let mut space = entity_heap::EntityHeap::new();
+    let start = time::Instant::now();
+
+    let mut seed = 1;
+    for _ in 0..EVENTS {
+        seed = next_random(seed);
+        let delay = (seed % (10 * units::SEC) as u64) as units::Duration;
+        time.enqueue(Nop, delay).unwrap();
+    }
+    while time.next().is_some() {
+        let until = time.now() + FRAME;
+        time.simulate(&mut space, until);
+    }
+
+    start.elapsed()
+}
+
+fn run_cluster(mut time: event_queue::EventQueue) -> time::Duration {
+    let mut space = entity_heap::EntityHeap::new();
+    let start = time::Instant::now();
+
+    // 4^8 = 65536 leaves, 87381 events in total
+    time.enqueue(Split { depth: 8, seed: 1 }, 0).unwrap();
+    while time.next().is_some() {
+        let until = time.now() + FRAME;
+        time.simulate(&mut space, until);
+    }
+
+    start.elapsed()
+}
+
+fn main() {
+    let heap = run_flat(event_queue::EventQueue::new());
+    let wheel = run_flat(event_queue::EventQueue::with_timing_wheel());
+    println!("{} scattered events", EVENTS);
+    println!("  binary heap:  {:?}", heap);
+    println!("  timing wheel: {:?}", wheel);
+
+    let heap = run_cluster(event_queue::EventQueue::new());
+    let wheel = run_cluster(event_queue::EventQueue::with_timing_wheel());
+    println!("nested cluster of depth 8");
+    println!("  binary heap:  {:?}", heap);
+    println!("  timing wheel: {:?}", wheel);
+}
@@ -0,0 +1,329 @@
+use std::rc;
+
+use entity_heap;
+use event_queue;
+use physics;
+use units;
+
+// what happens when two entities touch
+pub trait Response {
+    fn collide(
+        &self,
+        space: &mut entity_heap::EntityHeap,
+        time: &mut event_queue::EventQueue,
+        first: entity_heap::UID,
+        second: entity_heap::UID,
+    ) -> Result<(), event_queue::EventError>;
+}
+
+// the moment two entities are predicted to touch
+// the prediction only holds while both bodies keep moving the way they were,
+//   so if either has bounced by the time this runs, or been carried off
+//   by an anchor that has moved, then nothing happens,
+//   and whatever changed it is responsible for predicting again
+pub struct CollisionEvent {
+    first: entity_heap::UID,
+    second: entity_heap::UID,
+    first_body: physics::Body,
+    second_body: physics::Body,
+    response: rc::Rc<Response>,
+}
+
+impl CollisionEvent {
+    fn still_valid(&self, space: &entity_heap::EntityHeap) -> bool {
+        let unchanged = |uid, expected| {
+            space.entity(uid)
+                 .and_then(entity_heap::Entity::body)
+                 .is_some_and(|body| body == expected)
+        };
+        unchanged(self.first, &self.first_body)
+            && unchanged(self.second, &self.second_body)
+    }
+}
+
+impl event_queue::Event for CollisionEvent {
+    fn invoke(
+        self: Self,
+        space: &mut entity_heap::EntityHeap,
+        time: &mut event_queue::EventQueue
+    ) -> Result<(), event_queue::EventError> {
+        if !self.still_valid(space) {
+            return Ok(());
+        }
+        self.response.collide(space, time, self.first, self.second)
+    }
+
+    fn kind(&self) -> &'static str {
+        "CollisionEvent"
+    }
+
+    fn describe(&self) -> String {
+        format!("collide {} with {}", self.first, self.second)
+    }
+
+    fn entities(&self) -> Vec<entity_heap::UID> {
+        vec![self.first, self.second]
+    }
+}
+
+// as far as predict could look ahead without the two entities touching
+// if neither has bounced by then, it carries on looking from here
+pub struct RecheckEvent {
+    pending: CollisionEvent,
+}
+
+impl event_queue::Event for RecheckEvent {
+    fn invoke(
+        self: Self,
+        space: &mut entity_heap::EntityHeap,
+        time: &mut event_queue::EventQueue
+    ) -> Result<(), event_queue::EventError> {
+        if !self.pending.still_valid(space) {
+            return Ok(());
+        }
+        let CollisionEvent { first, second, response, .. } = self.pending;
+        predict(space, time, first, second, response)?;
+        Ok(())
+    }
+
+    fn kind(&self) -> &'static str {
+        "RecheckEvent"
+    }
+
+    fn describe(&self) -> String {
+        let pending = &self.pending;
+        format!("look again at {} and {}", pending.first, pending.second)
+    }
+
+    fn entities(&self) -> Vec<entity_heap::UID> {
+        vec![self.pending.first, self.pending.second]
+    }
+}
+
+// schedules a CollisionEvent for when the two entities will first touch,
+//   treating each as a circle the size of its shape
+// if that is further off than contact_time looks, this schedules
+//   a RecheckEvent instead, and gives that
+// gives None if they never will touch, or if either has no body or shape
+pub fn predict(
+    space: &entity_heap::EntityHeap,
+    time: &mut event_queue::EventQueue,
+    first: entity_heap::UID,
+    second: entity_heap::UID,
+    response: rc::Rc<Response>,
+) -> Result<Option<event_queue::EventID>, event_queue::EventError> {
+    let (first_body, first_radius) = match circle(space, first)? {
+        Some(circle) => circle,
+        None => return Ok(None),
+    };
+    let (second_body, second_radius) = match circle(space, second)? {
+        Some(circle) => circle,
+        None => return Ok(None),
+    };
+
+    let contact = physics::contact_time(
+        &first_body,
+        first_radius,
+        &second_body,
+        second_radius,
+        time.now(),
+    );
+    let event = CollisionEvent {
+        first,
+        second,
+        first_body,
+        second_body,
+        response,
+    };
+    let id = match contact {
+        physics::Contact::At(contact_time) => {
+            time.enqueue_at(event, contact_time)?
+        },
+        physics::Contact::RecheckAt(recheck_time) => {
+            let event = RecheckEvent { pending: event };
+            time.enqueue_at(event, recheck_time)?
+        },
+        physics::Contact::Never => return Ok(None),
+    };
+    Ok(Some(id))
+}
+
+fn circle(
+    space: &entity_heap::EntityHeap,
+    uid: entity_heap::UID,
+) -> Result<Option<(physics::Body, units::Scalar)>, entity_heap::EntityError> {
+    let ent = space
+        .entity(uid)
+        .ok_or(entity_heap::EntityError::MissingEntity(uid))?;
+    Ok(match (ent.body(), ent.shape()) {
+        (Some(body), Some(shape)) => Some((body.snapshot(), shape.radius)),
+        _ => None,
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell;
+
+    use super::*;
+    use forms::effects;
+
+    struct Clear;
+
+    impl effects::Effect for Clear {
+        fn color(&self) -> [u8; 4] {
+            [0, 0, 0, 0]
+        }
+    }
+
+    struct Ball {
+        body: physics::Body,
+        shape: effects::Circle,
+    }
+
+    impl entity_heap::AsEntity for Ball {
+        const KIND: &'static str = "Ball";
+
+        fn body(&self) -> Option<&physics::Body> {
+            Some(&self.body)
+        }
+
+        fn body_mut(&mut self) -> Option<&mut physics::Body> {
+            Some(&mut self.body)
+        }
+
+        fn shape(&self) -> Option<&effects::Circle> {
+            Some(&self.shape)
+        }
+    }
+
+    fn ball(
+        space: &mut entity_heap::EntityHeap,
+        body: physics::Body,
+    ) -> entity_heap::UID {
+        let color = rc::Rc::new(Clear);
+        let shape = effects::Circle { color, radius: units::DOT };
+        entity_heap::new_entity(space, Ball { body, shape }).uid()
+    }
+
+    fn along(x: units::Scalar) -> units::Vec2 {
+        units::Vec2 { x, y: 0 }
+    }
+
+    // counts how many times they meet, and leaves them be
+    struct Count {
+        hits: rc::Rc<cell::Cell<u32>>,
+    }
+
+    impl Response for Count {
+        fn collide(
+            &self,
+            _space: &mut entity_heap::EntityHeap,
+            _time: &mut event_queue::EventQueue,
+            _first: entity_heap::UID,
+            _second: entity_heap::UID,
+        ) -> Result<(), event_queue::EventError> {
+            self.hits.set(self.hits.get() + 1);
+            Ok(())
+        }
+    }
+
+    // turns both around, then looks for the next time they meet
+    struct Reverse {
+        hits: rc::Rc<cell::Cell<u32>>,
+    }
+
+    impl Response for Reverse {
+        fn collide(
+            &self,
+            space: &mut entity_heap::EntityHeap,
+            time: &mut event_queue::EventQueue,
+            first: entity_heap::UID,
+            second: entity_heap::UID,
+        ) -> Result<(), event_queue::EventError> {
+            self.hits.set(self.hits.get() + 1);
+            let now = time.now();
+            for &uid in &[first, second] {
+                let body = space
+                    .entity_mut(uid)
+                    .and_then(entity_heap::Entity::body_mut)
+                    .ok_or(entity_heap::EntityError::MissingEntity(uid))?;
+                let velocity = -body.velocity(now);
+                body.bounce(velocity, now);
+            }
+            let hits = rc::Rc::clone(&self.hits);
+            let response = rc::Rc::new(Reverse { hits });
+            predict(space, time, first, second, response)?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn circles_that_bounce_apart_are_not_hit_again() {
+        let mut space = entity_heap::EntityHeap::new();
+        let mut time = event_queue::EventQueue::new();
+        let left = physics::Body::new(units::ZERO_VEC, along(3), 0);
+        let left = ball(&mut space, left);
+        let right = physics::Body::new(along(10 * units::DOT), along(-2), 0);
+        let right = ball(&mut space, right);
+        let hits = rc::Rc::new(cell::Cell::new(0));
+        let response = rc::Rc::new(Reverse { hits: rc::Rc::clone(&hits) });
+        predict(&space, &mut time, left, right, response).unwrap();
+
+        let report = time.simulate_bounded(&mut space, 10 * units::SEC, 100);
+        assert_eq!(report.progress, event_queue::Progress::Finished);
+        assert!(report.failures.is_empty());
+        assert_eq!(hits.get(), 1);
+        assert_eq!(time.pending_events().count(), 0);
+    }
+
+    #[test]
+    fn overlapping_circles_only_touch_while_closing() {
+        let touching = along(units::DOT);
+        let still = physics::Body::new_frozen(units::ZERO_VEC);
+        let closing = physics::Body::new(touching, -touching / 100, 0);
+        let leaving = physics::Body::new(touching, touching / 100, 0);
+        let radius = units::DOT;
+        let contact = physics::contact_time;
+        let at_once = physics::Contact::At(0);
+        assert_eq!(contact(&closing, radius, &still, radius, 0), at_once);
+        let never = physics::Contact::Never;
+        assert_eq!(contact(&leaving, radius, &still, radius, 0), never);
+    }
+
+    #[test]
+    fn circles_too_far_off_to_search_are_looked_at_again() {
+        let mut space = entity_heap::EntityHeap::new();
+        let mut time = event_queue::EventQueue::new();
+        // this reaches the still one after about 99 seconds
+        let still = physics::Body::new_frozen(along(5000 * units::DOT));
+        let mover = physics::Body::new_accelerating(
+            units::ZERO_VEC,
+            units::ZERO_VEC,
+            along(1),
+            0
+        );
+        let radius = units::DOT;
+        let contact = physics::contact_time(&mover, radius, &still, radius, 0);
+        match contact {
+            physics::Contact::RecheckAt(recheck) => {
+                assert!(recheck > physics::SEARCH_HORIZON);
+            },
+            _ => panic!("expected a recheck, not {:?}", contact),
+        }
+
+        let mover = ball(&mut space, mover);
+        let still = ball(&mut space, still);
+        let hits = rc::Rc::new(cell::Cell::new(0));
+        let response = rc::Rc::new(Count { hits: rc::Rc::clone(&hits) });
+        predict(&space, &mut time, mover, still, response).unwrap();
+
+        let report = time.simulate_bounded(&mut space, 99 * units::SEC, 100);
+        assert!(report.failures.is_empty());
+        assert_eq!(hits.get(), 0);
+        let report = time.simulate_bounded(&mut space, 101 * units::SEC, 100);
+        assert!(report.failures.is_empty());
+        assert_eq!(hits.get(), 1);
+    }
+}
@@ -1,96 +1,670 @@
-use std::collections;
+use std::any;
+use std::error;
+use std::fmt;
+use std::hash;
+use std::marker;
+use std::rc;
+use std::slice;
 
 use forms::effects;
+use physics;
 
-pub trait AsEntity where Self: Sized {
-    fn as_entity(Self) -> Entity;
-    fn downcast(Entity) -> Result<Self, Entity>;
-    fn downcast_ref(&Entity) -> Option<&Self>;
-    fn downcast_mut(&mut Entity) -> Option<&mut Self>;
-}
+// implementing this is all it takes for a type to live in an EntityHeap,
+//   so new kinds of entity can be defined outside of this crate
+pub trait AsEntity where Self: 'static + Sized {
+    // a name for this kind of entity, for errors and debugging
+    const KIND: &'static str;
 
-macro_rules! as_entity {
-    ($var: ident => $typ: ty) => {
-        impl AsEntity for $typ {
-            fn as_entity(matter: Self) -> Entity {
-                Entity::$var(matter)
-            }
-            fn downcast(ent: Entity) -> Result<Self, Entity> {
-                if let Entity::$var(matter) = ent {
-                    Ok(matter)
-                } else {
-                    Err(ent)
-                }
-            }
-            fn downcast_ref(ent: &Entity) -> Option<&Self> {
-                if let Entity::$var(ref matter) = *ent {
-                    Some(matter)
-                } else {
-                    None
-                }
-            }
-            fn downcast_mut(ent: &mut Entity) -> Option<&mut Self> {
-                if let Entity::$var(ref mut matter) = *ent {
-                    Some(matter)
-                } else {
-                    None
-                }
-            }
+    fn as_entity(matter: Self) -> Entity {
+        Entity {
+            matter: Box::new(matter),
+            lineage: Lineage::new(),
         }
     }
-}
 
-macro_rules! entity_definition {
-    {$($var: ident($typ: ty)),+,} => {
-        pub enum Entity {
-            $($var($typ)),+
+    fn downcast(ent: Entity) -> Result<Self, Entity> {
+        if ent.is::<Self>() {
+            let any = ent.matter.into_any();
+            Ok(*any.downcast().expect("Entity::is lied"))
+        } else {
+            Err(ent)
         }
+    }
+
+    fn downcast_ref(ent: &Entity) -> Option<&Self> {
+        ent.matter.as_any().downcast_ref()
+    }
+
+    fn downcast_mut(ent: &mut Entity) -> Option<&mut Self> {
+        ent.matter.as_any_mut().downcast_mut()
+    }
+
+    // where the entity is, for entities that are anywhere in particular
+    fn body(&self) -> Option<&physics::Body> {
+        None
+    }
+
+    // for entities that other things can push around
+    fn body_mut(&mut self) -> Option<&mut physics::Body> {
+        None
+    }
 
-        $( as_entity!($var => $typ); )+
+    // what the entity looks like, for entities that can be seen
+    fn shape(&self) -> Option<&effects::Circle> {
+        None
     }
 }
 
-// TODO maybe use a C-enum and downcasting instead?
-// that could be better in a number of ways
-//  - less coupling between this and the entities' definitions
-//  - pointers will definitely point to the right type
-//  - polymorphic idiom could emerge
-//  - compatible with dynamic entity definitions/modding API (future projects)
-entity_definition! {
-    Bolt(effects::Bolt),
-    Smoke(effects::Smoke),
+// the object safe part of AsEntity
+trait Matter {
+    fn kind(&self) -> &'static str;
+    fn body(&self) -> Option<&physics::Body>;
+    fn body_mut(&mut self) -> Option<&mut physics::Body>;
+    fn shape(&self) -> Option<&effects::Circle>;
+    fn as_any(&self) -> &any::Any;
+    fn as_any_mut(&mut self) -> &mut any::Any;
+    fn into_any(self: Box<Self>) -> Box<any::Any>;
+}
+
+impl<T> Matter for T
+    where T: AsEntity
+{
+    fn kind(&self) -> &'static str {
+        T::KIND
+    }
+
+    fn body(&self) -> Option<&physics::Body> {
+        AsEntity::body(self)
+    }
+
+    fn body_mut(&mut self) -> Option<&mut physics::Body> {
+        AsEntity::body_mut(self)
+    }
+
+    fn shape(&self) -> Option<&effects::Circle> {
+        AsEntity::shape(self)
+    }
+
+    fn as_any(&self) -> &any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut any::Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<any::Any> {
+        self
+    }
 }
 
+pub struct Entity {
+    matter: Box<Matter>,
+    lineage: Lineage,
+}
 
 impl Entity {
-    pub fn expect<T>(self, err: &str) -> T
+    pub fn new<T>(matter: T) -> Entity
         where T: AsEntity
     {
-        if let Ok(val) = AsEntity::downcast(self) {
-            val
-        } else {
-            panic!("Downcasted bad entity UID: {}", err);
+        AsEntity::as_entity(matter)
+    }
+
+    pub fn kind(&self) -> &'static str {
+        self.matter.kind()
+    }
+
+    pub fn lineage(&self) -> &Lineage {
+        &self.lineage
+    }
+
+    pub fn body(&self) -> Option<&physics::Body> {
+        self.matter.body()
+    }
+
+    pub fn body_mut(&mut self) -> Option<&mut physics::Body> {
+        self.matter.body_mut()
+    }
+
+    pub fn shape(&self) -> Option<&effects::Circle> {
+        self.matter.shape()
+    }
+
+    pub fn type_id(&self) -> any::TypeId {
+        self.matter.as_any().type_id()
+    }
+
+    pub fn is<T>(&self) -> bool
+        where T: AsEntity
+    {
+        self.type_id() == any::TypeId::of::<T>()
+    }
+
+    pub fn downcast<T>(self) -> Result<T, Entity>
+        where T: AsEntity
+    {
+        AsEntity::downcast(self)
+    }
+
+    pub fn downcast_ref<T>(&self) -> Option<&T>
+        where T: AsEntity
+    {
+        AsEntity::downcast_ref(self)
+    }
+
+    pub fn downcast_mut<T>(&mut self) -> Option<&mut T>
+        where T: AsEntity
+    {
+        AsEntity::downcast_mut(self)
+    }
+
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntityError {
+    MissingEntity(UID),
+    WrongEntityKind {
+        uid: UID,
+        expected: &'static str,
+        found: &'static str,
+    },
+}
+
+impl fmt::Display for EntityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EntityError::MissingEntity(uid) => write!(
+                f,
+                "no entity with UID {}",
+                uid,
+            ),
+            EntityError::WrongEntityKind { uid, expected, found } => write!(
+                f,
+                "expected entity {} to be {} but found {}",
+                uid,
+                expected,
+                found,
+            ),
         }
     }
 }
 
-// TODO rand
-pub fn new_entity<T>(space: &mut EntityHeap, matter: T) -> UID
+impl error::Error for EntityError {
+}
+
+pub fn new_entity<T>(space: &mut EntityHeap, matter: T) -> EntityRef<T>
     where T: AsEntity
 {
-    let mut uid = space.len() as UID;
-    while space.contains_key(&uid) {
-        uid += 1;
-    }
-    let ent = AsEntity::as_entity(matter);
-    space.insert(uid, ent);
-    uid
+    new_entity_from(space, matter, Lineage::new())
+}
+
+// adds an entity that was spawned by some caster or other entity
+pub fn new_entity_from<T>(
+    space: &mut EntityHeap,
+    matter: T,
+    lineage: Lineage,
+) -> EntityRef<T>
+    where T: AsEntity
+{
+    let mut ent = AsEntity::as_entity(matter);
+    ent.lineage = lineage;
+    let uid = space.insert(ent);
+    EntityRef::new(uid)
 }
 
 
+// the low 32 bits pick a slot in the heap,
+//   and the high 32 bits say which occupant of that slot is meant
 pub type UID = u64;
 
+fn make_uid(index: u32, generation: u32) -> UID {
+    (generation as UID) << 32 | index as UID
+}
+
+fn split_uid(uid: UID) -> (u32, u32) {
+    (uid as u32, (uid >> 32) as u32)
+}
+
+pub type ListenerID = u64;
+
+// whoever is responsible for a spell, e.g. a player
+pub type CasterID = u64;
+
+// where an entity came from
+// ancestors are remembered even after they are removed from the heap,
+//   so that the children of a landed bolt still know about each other
+#[derive(Clone, Debug, Default)]
+pub struct Lineage {
+    pub caster: Option<CasterID>,
+    ancestry: Option<rc::Rc<Ancestor>>,
+}
+
+#[derive(Debug)]
+struct Ancestor {
+    uid: UID,
+    parent: Option<rc::Rc<Ancestor>>,
+}
+
+impl Lineage {
+    // no caster and no parent, e.g. for parts of the world
+    pub fn new() -> Lineage {
+        Lineage {
+            caster: None,
+            ancestry: None,
+        }
+    }
+
+    pub fn cast_by(caster: CasterID) -> Lineage {
+        Lineage {
+            caster: Some(caster),
+            ancestry: None,
+        }
+    }
+
+    // the lineage for something spawned by `parent`,
+    //   given that this is the lineage of `parent`
+    pub fn child_of(&self, parent: UID) -> Lineage {
+        let ancestor = Ancestor {
+            uid: parent,
+            parent: self.ancestry.clone(),
+        };
+        Lineage {
+            caster: self.caster,
+            ancestry: Some(rc::Rc::new(ancestor)),
+        }
+    }
+
+    pub fn parent(&self) -> Option<UID> {
+        self.ancestry
+            .as_ref()
+            .map(|ancestor| ancestor.uid)
+    }
+
+    // parent first, then grandparent, and so on
+    pub fn ancestors(&self) -> Vec<UID> {
+        let mut ancestors = Vec::new();
+        let mut next = self.ancestry.as_ref();
+        while let Some(ancestor) = next {
+            ancestors.push(ancestor.uid);
+            next = ancestor.parent.as_ref();
+        }
+        ancestors
+    }
+
+    pub fn descends_from(&self, uid: UID) -> bool {
+        self.ancestors().contains(&uid)
+    }
+}
+
+// hooks for watching entities come and go,
+//   e.g. for effects, kill counters, or keeping other structures in sync
+pub trait Lifecycle {
+    // called just before the entity is added
+    fn spawned(&mut self, _uid: UID, _ent: &Entity) {
+    }
+
+    // called just after the entity is removed, before it is dropped
+    fn despawned(&mut self, _uid: UID, _ent: &Entity) {
+    }
+}
+
+// a UID that knows what kind of entity it refers to,
+//   so that looking it up gives the entity itself rather than an Entity
+pub struct EntityRef<T> {
+    uid: UID,
+    // fn() -> T so that the reference doesn't act like it owns a T
+    kind: marker::PhantomData<fn() -> T>,
+}
+
+impl<T> EntityRef<T> {
+    fn new(uid: UID) -> EntityRef<T> {
+        EntityRef { uid, kind: marker::PhantomData }
+    }
+
+    pub fn uid(&self) -> UID {
+        self.uid
+    }
+}
+
+// derive would require T to implement each of these too
+impl<T> Clone for EntityRef<T> {
+    fn clone(&self) -> EntityRef<T> {
+        *self
+    }
+}
+
+impl<T> Copy for EntityRef<T> {
+}
+
+impl<T> PartialEq for EntityRef<T> {
+    fn eq(&self, other: &EntityRef<T>) -> bool {
+        self.uid == other.uid
+    }
+}
+
+impl<T> Eq for EntityRef<T> {
+}
+
+impl<T> hash::Hash for EntityRef<T> {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.uid.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for EntityRef<T>
+    where T: AsEntity
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}({})", T::KIND, self.uid)
+    }
+}
+
+struct Slot {
+    generation: u32,
+    // where the occupant is in EntityHeap::dense, if there is one
+    dense: Option<usize>,
+}
+
 // heap in the memory sense not the queue sense
-pub type EntityHeap = collections::HashMap<UID, Entity>;
+// entities are packed together so that iterating over them is cheap,
+//   and found by UID through a table of slots
+// a slot's generation goes up every time it is emptied,
+//   so a UID held onto after its entity is gone can't find a newer one
+pub struct EntityHeap {
+    slots: Vec<Slot>,
+    // empty slots, ready to be reused
+    free: Vec<u32>,
+    dense: Vec<(UID, Entity)>,
+    next_listener: ListenerID,
+    listeners: Vec<(ListenerID, Box<Lifecycle>)>,
+}
+
+impl Default for EntityHeap {
+    fn default() -> EntityHeap {
+        EntityHeap::new()
+    }
+}
+
+impl EntityHeap {
+    pub fn new() -> EntityHeap {
+        EntityHeap {
+            slots: Vec::new(),
+            free: Vec::new(),
+            dense: Vec::new(),
+            next_listener: 0,
+            listeners: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.dense.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    pub fn contains(&self, uid: UID) -> bool {
+        self.dense_index(uid).is_some()
+    }
+
+    pub fn entity(&self, uid: UID) -> Option<&Entity> {
+        self.dense_index(uid)
+            .map(|index| &self.dense[index].1)
+    }
+
+    pub fn entity_mut(&mut self, uid: UID) -> Option<&mut Entity> {
+        match self.dense_index(uid) {
+            Some(index) => Some(&mut self.dense[index].1),
+            None => None,
+        }
+    }
+
+    pub fn remove_entity(&mut self, uid: UID) -> Option<Entity> {
+        let index = self.dense_index(uid)?;
+        let (_, ent) = self.dense.swap_remove(index);
+        if let Some(&(moved, _)) = self.dense.get(index) {
+            let (slot, _) = split_uid(moved);
+            self.slots[slot as usize].dense = Some(index);
+        }
+
+        let (slot_index, _) = split_uid(uid);
+        let slot = &mut self.slots[slot_index as usize];
+        slot.dense = None;
+        // a slot that has run out of generations is never used again
+        if let Some(generation) = slot.generation.checked_add(1) {
+            slot.generation = generation;
+            self.free.push(slot_index);
+        }
+
+        for &mut (_, ref mut listener) in &mut self.listeners {
+            listener.despawned(uid, &ent);
+        }
+        Some(ent)
+    }
+
+    fn insert(&mut self, ent: Entity) -> UID {
+        let index =
+            if let Some(index) = self.free.pop() {
+                index
+            } else {
+                let index = self.slots.len();
+                assert!(index <= u32::MAX as usize, "EntityHeap is full");
+                self.slots.push(Slot { generation: 0, dense: None });
+                index as u32
+            };
+        let slot = &mut self.slots[index as usize];
+        let uid = make_uid(index, slot.generation);
+        slot.dense = Some(self.dense.len());
+
+        for &mut (_, ref mut listener) in &mut self.listeners {
+            listener.spawned(uid, &ent);
+        }
+        self.dense.push((uid, ent));
+        uid
+    }
+
+    fn dense_index(&self, uid: UID) -> Option<usize> {
+        let (index, generation) = split_uid(uid);
+        self.slots
+            .get(index as usize)
+            .filter(|slot| slot.generation == generation)
+            .and_then(|slot| slot.dense)
+    }
+
+    pub fn add_listener<L>(&mut self, listener: L) -> ListenerID
+        where L: 'static + Lifecycle
+    {
+        let id = self.next_listener;
+        self.next_listener += 1;
+
+        self.listeners.push((id, Box::new(listener)));
+        id
+    }
+
+    // returns false if there was no such listener
+    pub fn remove_listener(&mut self, id: ListenerID) -> bool {
+        let before = self.listeners.len();
+        self.listeners.retain(|&(other, _)| other != id);
+        self.listeners.len() != before
+    }
+
+    // checks that an untyped UID refers to a T
+    pub fn reference<T>(&self, uid: UID) -> Option<EntityRef<T>>
+        where T: AsEntity
+    {
+        self.entity(uid)
+            .and_then(AsEntity::downcast_ref)
+            .map(|_: &T| EntityRef::new(uid))
+    }
+
+    pub fn get<T>(&self, target: EntityRef<T>) -> Option<&T>
+        where T: AsEntity
+    {
+        self.entity(target.uid)
+            .and_then(AsEntity::downcast_ref)
+    }
+
+    pub fn get_mut<T>(&mut self, target: EntityRef<T>) -> Option<&mut T>
+        where T: AsEntity
+    {
+        self.entity_mut(target.uid)
+            .and_then(AsEntity::downcast_mut)
+    }
+
+    // every entity of one kind
+    pub fn of_kind<'a, T>(
+        &'a self
+    ) -> impl Iterator<Item = (EntityRef<T>, &'a T)> + 'a
+        where T: AsEntity
+    {
+        self.iter().filter_map(|(&uid, ent)| {
+            AsEntity::downcast_ref(ent)
+                .map(|matter| (EntityRef::new(uid), matter))
+        })
+    }
+
+    pub fn of_kind_mut<'a, T>(
+        &'a mut self
+    ) -> impl Iterator<Item = (EntityRef<T>, &'a mut T)> + 'a
+        where T: AsEntity
+    {
+        self.iter_mut().filter_map(|(&uid, ent)| {
+            AsEntity::downcast_mut(ent)
+                .map(|matter| (EntityRef::new(uid), matter))
+        })
+    }
+
+    // every entity that has both a body and a shape,
+    //   i.e. everything that can be drawn
+    pub fn bodies_and_shapes<'a>(
+        &'a self
+    ) -> impl Iterator<Item = (UID, &'a physics::Body, &'a effects::Circle)>
+        + 'a
+    {
+        self.iter().filter_map(|(&uid, ent)| {
+            match (ent.body(), ent.shape()) {
+                (Some(body), Some(shape)) => Some((uid, body, shape)),
+                _ => None,
+            }
+        })
+    }
+
+    pub fn filter<'a, P>(
+        &'a self,
+        mut predicate: P
+    ) -> impl Iterator<Item = (UID, &'a Entity)> + 'a
+        where P: 'a + FnMut(&Entity) -> bool
+    {
+        self.iter()
+            .filter(move |&(_, ent)| predicate(ent))
+            .map(|(&uid, ent)| (uid, ent))
+    }
+
+    pub fn lineage(&self, uid: UID) -> Option<&Lineage> {
+        self.entity(uid).map(Entity::lineage)
+    }
+
+    // every entity spawned by `uid`, by its children, and so on,
+    //   in order of UID
+    pub fn descendants(&self, uid: UID) -> Vec<UID> {
+        let mut found: Vec<UID> = self
+            .filter(|ent| ent.lineage.descends_from(uid))
+            .map(|(uid, _)| uid)
+            .collect();
+        found.sort();
+        found
+    }
+
+    // every entity that came from one caster's spells, in order of UID
+    pub fn cast_by(&self, caster: CasterID) -> Vec<UID> {
+        let mut found: Vec<UID> = self
+            .filter(|ent| ent.lineage.caster == Some(caster))
+            .map(|(uid, _)| uid)
+            .collect();
+        found.sort();
+        found
+    }
+
+    // leaves the entity alone if it is somehow not a T
+    pub fn remove<T>(&mut self, target: EntityRef<T>) -> Result<T, EntityError>
+        where T: AsEntity
+    {
+        let uid = target.uid;
+        let found = self
+            .entity(uid)
+            .ok_or(EntityError::MissingEntity(uid))?
+            .kind();
+        if self.get(target).is_none() {
+            let expected = T::KIND;
+            return Err(EntityError::WrongEntityKind { uid, expected, found });
+        }
+        self.remove_entity(uid)
+            .and_then(|ent| AsEntity::downcast(ent).ok())
+            .ok_or(EntityError::MissingEntity(uid))
+    }
+
+    pub fn iter<'a>(&'a self) -> Iter<'a> {
+        Iter { dense: self.dense.iter() }
+    }
+
+    pub fn iter_mut<'a>(&'a mut self) -> IterMut<'a> {
+        IterMut { dense: self.dense.iter_mut() }
+    }
+}
+
+pub struct Iter<'a> {
+    dense: slice::Iter<'a, (UID, Entity)>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a UID, &'a Entity);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.dense
+            .next()
+            .map(|entry| (&entry.0, &entry.1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.dense.size_hint()
+    }
+}
+
+pub struct IterMut<'a> {
+    dense: slice::IterMut<'a, (UID, Entity)>,
+}
+
+impl<'a> Iterator for IterMut<'a> {
+    type Item = (&'a UID, &'a mut Entity);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.dense
+            .next()
+            .map(|entry| (&entry.0, &mut entry.1))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.dense.size_hint()
+    }
+}
+
+impl<'a> IntoIterator for &'a EntityHeap {
+    type Item = (&'a UID, &'a Entity);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut EntityHeap {
+    type Item = (&'a UID, &'a mut Entity);
+    type IntoIter = IterMut<'a>;
+
+    fn into_iter(self) -> IterMut<'a> {
+        self.iter_mut()
+    }
+}
 
 
@@ -0,0 +1,786 @@
+use std::any;
+use std::cmp;
+use std::collections;
+use std::collections::binary_heap;
+use std::error;
+use std::fmt;
+use std::vec;
+
+use units;
+use entity_heap;
+
+mod wheel;
+
+pub trait Event {
+    fn invoke(
+        self: Self,
+        space: &mut entity_heap::EntityHeap,
+        time: &mut EventQueue,
+    ) -> Result<(), EventError>;
+
+    // names the kind of event, for grouping events when debugging
+    fn kind(&self) -> &'static str {
+        any::type_name::<Self>()
+    }
+
+    // describes this particular event, e.g. which entities it acts on
+    fn describe(&self) -> String {
+        self.kind().to_string()
+    }
+
+    // the entities that this event acts on, as far as observers care
+    fn entities(&self) -> Vec<entity_heap::UID> {
+        Vec::new()
+    }
+}
+
+// polymorphise Event monomorphisms,
+//   since by-value makes more sense to write,
+//   and potentially allows implementors to use their own code
+//   outside of event contexts
+trait PolyEvent: Event {
+    fn invoke_box(
+        self: Box<Self>,
+        space: &mut entity_heap::EntityHeap,
+        time: &mut EventQueue,
+    ) -> Result<(), EventError>;
+}
+
+// note the resemlence to FnBox
+impl<T> PolyEvent for T
+    where T: Event
+{
+    fn invoke_box(
+        self: Box<Self>,
+        space: &mut entity_heap::EntityHeap,
+        time: &mut EventQueue,
+    ) -> Result<(), EventError> {
+        self.invoke(space, time)
+    }
+}
+
+// periodic events are stored once and copied out each time they fire
+trait PolyRepeat {
+    fn instance(&self) -> Box<PolyEvent>;
+    fn as_event(&self) -> &PolyEvent;
+}
+
+impl<T> PolyRepeat for T
+    where T: 'static + Event + Clone
+{
+    fn instance(&self) -> Box<PolyEvent> {
+        Box::new(self.clone())
+    }
+
+    fn as_event(&self) -> &PolyEvent {
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Repeat {
+    Forever,
+    // total number of times to fire, including the first
+    Times(u64),
+    // keep firing as long as the firing time is no later than this
+    Until(units::Time),
+}
+
+struct Periodic {
+    event: Box<PolyRepeat>,
+    interval: units::Duration,
+    repeat: Repeat,
+}
+
+enum CallBack {
+    Once(Box<PolyEvent>),
+    Repeat(Periodic),
+}
+
+impl CallBack {
+    fn event(&self) -> &PolyEvent {
+        match *self {
+            CallBack::Once(ref event) => &**event,
+            CallBack::Repeat(ref periodic) => periodic.event.as_event(),
+        }
+    }
+
+    fn interval(&self) -> Option<units::Duration> {
+        match *self {
+            CallBack::Once(_) => None,
+            CallBack::Repeat(ref periodic) => Some(periodic.interval),
+        }
+    }
+}
+
+
+pub type EventID = u64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScheduleError {
+    // events can't be scheduled before the current time,
+    //   since the queue would have to run them without rewinding the clock
+    InPast {
+        now: units::Time,
+        requested: units::Time,
+    },
+    // the event has already run or been cancelled
+    NotPending(EventID),
+    // periodic events need a positive interval, and must fire at least once
+    BadPeriod {
+        interval: units::Duration,
+        repeat: Repeat,
+    },
+    // the delay takes the time past what units::Time can hold
+    OutOfRange {
+        now: units::Time,
+        delay: units::Duration,
+    },
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScheduleError::InPast { now, requested } => write!(
+                f,
+                "cannot schedule an event at {} since it is already {}",
+                requested,
+                now,
+            ),
+            ScheduleError::NotPending(id) => write!(
+                f,
+                "event {} is not pending",
+                id,
+            ),
+            ScheduleError::BadPeriod { interval, repeat } => write!(
+                f,
+                "cannot repeat an event every {} ticks {:?}",
+                interval,
+                repeat,
+            ),
+            ScheduleError::OutOfRange { now, delay } => write!(
+                f,
+                "cannot schedule an event {} ticks after {}",
+                delay,
+                now,
+            ),
+        }
+    }
+}
+
+impl error::Error for ScheduleError {
+}
+
+// something an event expected to be true of the simulation wasn't
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventError {
+    Entity(entity_heap::EntityError),
+    Schedule(ScheduleError),
+    Overflow(units::Overflow),
+}
+
+impl From<entity_heap::EntityError> for EventError {
+    fn from(err: entity_heap::EntityError) -> EventError {
+        EventError::Entity(err)
+    }
+}
+
+impl From<ScheduleError> for EventError {
+    fn from(err: ScheduleError) -> EventError {
+        EventError::Schedule(err)
+    }
+}
+
+impl From<units::Overflow> for EventError {
+    fn from(err: units::Overflow) -> EventError {
+        EventError::Overflow(err)
+    }
+}
+
+impl fmt::Display for EventError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EventError::Entity(ref err) => fmt::Display::fmt(err, f),
+            EventError::Schedule(ref err) => fmt::Display::fmt(err, f),
+            EventError::Overflow(ref err) => fmt::Display::fmt(err, f),
+        }
+    }
+}
+
+impl error::Error for EventError {
+}
+
+// an event that returned an error when it was invoked
+#[derive(Clone, Debug)]
+pub struct Failure {
+    pub id: EventID,
+    pub execute_time: units::Time,
+    pub kind: &'static str,
+    pub error: EventError,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} (event {} at {}) failed: {}",
+            self.kind,
+            self.id,
+            self.execute_time,
+            self.error,
+        )
+    }
+}
+
+// the heap only orders ids, the events themselves live in a map
+//   so that they can be withdrawn or moved without digging through the heap
+// an element whose id is no longer pending, or whose sequence number has
+//   been superseded by a reschedule, is stale and gets discarded when it
+//   reaches the top
+struct QueueElement {
+    execute_time: units::Time,
+    sequence: u64,
+    id: EventID,
+}
+
+struct Pending {
+    execute_time: units::Time,
+    sequence: u64,
+    call_back: CallBack,
+}
+
+impl PartialEq for QueueElement {
+    fn eq(
+        self: &QueueElement,
+        other: &QueueElement
+    ) -> bool {
+        self.execute_time == other.execute_time
+            && self.sequence == other.sequence
+    }
+}
+
+impl Eq for QueueElement {
+}
+
+impl PartialOrd for QueueElement {
+    fn partial_cmp(
+        self: &QueueElement,
+        other: &QueueElement
+    ) -> Option<cmp::Ordering> {
+        Some(Ord::cmp(self, other))
+    }
+}
+
+// events run in order of execute_time,
+//   and events with the same execute_time run in the order they were
+//   scheduled, where rescheduling counts as scheduling anew
+// this makes a simulation reproducible given the same inputs
+impl Ord for QueueElement {
+    fn cmp(
+        self: &QueueElement,
+        other: &QueueElement
+    ) -> cmp::Ordering {
+        let this = (self.execute_time, self.sequence);
+        let that = (other.execute_time, other.sequence);
+        // lower time = higher priority
+        Ord::cmp(&this, &that).reverse()
+    }
+}
+
+// the structure that keeps QueueElements in the order they run
+trait Backend {
+    fn push(&mut self, element: QueueElement);
+    fn peek(&self) -> Option<&QueueElement>;
+    fn pop(&mut self) -> Option<QueueElement>;
+}
+
+impl Backend for binary_heap::BinaryHeap<QueueElement> {
+    fn push(&mut self, element: QueueElement) {
+        binary_heap::BinaryHeap::push(self, element);
+    }
+
+    fn peek(&self) -> Option<&QueueElement> {
+        binary_heap::BinaryHeap::peek(self)
+    }
+
+    fn pop(&mut self) -> Option<QueueElement> {
+        binary_heap::BinaryHeap::pop(self)
+    }
+}
+
+// a view of an event that is waiting in the queue
+pub struct PendingEvent<'a> {
+    pub id: EventID,
+    pub execute_time: units::Time,
+    // the time between firings, for periodic events
+    pub interval: Option<units::Duration>,
+    event: &'a PolyEvent,
+}
+
+impl<'a> PendingEvent<'a> {
+    pub fn kind(&self) -> &'static str {
+        self.event.kind()
+    }
+
+    pub fn describe(&self) -> String {
+        self.event.describe()
+    }
+}
+
+impl<'a> fmt::Debug for PendingEvent<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PendingEvent")
+         .field("id", &self.id)
+         .field("execute_time", &self.execute_time)
+         .field("interval", &self.interval)
+         .field("event", &self.describe())
+         .finish()
+    }
+}
+
+pub type ObserverID = u64;
+
+// what observers are told about each event that runs
+#[derive(Clone, Debug)]
+pub struct Invocation {
+    pub id: EventID,
+    pub time: units::Time,
+    pub kind: &'static str,
+    pub entities: Vec<entity_heap::UID>,
+}
+
+// hooks for watching the simulation without touching the events themselves
+//   e.g. combat logs, statistics, or sound and particle triggers
+pub trait Observer {
+    fn before(&mut self, _event: &Invocation) {
+    }
+
+    fn after(
+        &mut self,
+        _event: &Invocation,
+        _result: &Result<(), EventError>
+    ) {
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Progress {
+    // every event up to the requested time has run
+    Finished,
+    // the event budget ran out while events were still due,
+    //   the clock is left at the last event that ran
+    Interrupted,
+}
+
+// the outcome of simulating a stretch of time
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub progress: Progress,
+    // failed events are dropped, and the simulation carries on without them
+    pub failures: Vec<Failure>,
+}
+
+pub struct EventQueue {
+    current_time: units::Time,
+    next_id: EventID,
+    next_sequence: u64,
+    queue: Box<Backend>,
+    pending: collections::HashMap<EventID, Pending>,
+    next_observer: ObserverID,
+    observers: Vec<(ObserverID, Box<Observer>)>,
+}
+
+impl EventQueue {
+    pub fn new() -> EventQueue {
+        let queue = binary_heap::BinaryHeap::new();
+        EventQueue::with_backend(Box::new(queue))
+    }
+
+    // a queue backed by a hierarchical timing wheel instead of a heap,
+    //   which is cheaper when many events are scheduled close together
+    // events still run in exactly the same order
+    pub fn with_timing_wheel() -> EventQueue {
+        let queue = wheel::TimingWheel::new();
+        EventQueue::with_backend(Box::new(queue))
+    }
+
+    fn with_backend(queue: Box<Backend>) -> EventQueue {
+        EventQueue {
+            current_time: 0,
+            next_id: 0,
+            next_sequence: 0,
+            queue,
+            pending: collections::HashMap::new(),
+            next_observer: 0,
+            observers: Vec::new(),
+        }
+    }
+
+    pub fn now(&self) -> units::Time {
+        self.current_time
+    }
+
+    pub fn next(&self) -> Option<units::Time> {
+        self.queue
+            .peek()
+            .map(|qe| qe.execute_time)
+    }
+
+    pub fn invoke_next(
+        &mut self,
+        space: &mut entity_heap::EntityHeap
+    ) -> Result<(), Failure> {
+        let element =
+            if let Some(next) = self.queue.pop() {
+                next
+            } else {
+                return Ok(());
+            };
+        // the top of the heap is never stale, see discard_stale
+        let pending = self.pending.remove(&element.id);
+        self.discard_stale();
+        if element.execute_time > self.current_time {
+            self.current_time = element.execute_time;
+        }
+        let pending =
+            if let Some(pending) = pending {
+                pending
+            } else {
+                return Ok(());
+            };
+        let event = match pending.call_back {
+            CallBack::Once(event) => event,
+            CallBack::Repeat(periodic) => {
+                let event = periodic.event.instance();
+                // schedule the next firing before this one runs,
+                //   so that the event is able to cancel its own series
+                self.repeat(element.id, pending.execute_time, periodic);
+                event
+            },
+        };
+        let kind = event.kind();
+        let invocation =
+            if self.observers.is_empty() {
+                None
+            } else {
+                Some(Invocation {
+                    id: element.id,
+                    time: self.current_time,
+                    kind,
+                    entities: event.entities(),
+                })
+            };
+
+        if let Some(ref invocation) = invocation {
+            for &mut (_, ref mut observer) in &mut self.observers {
+                observer.before(invocation);
+            }
+        }
+        let result = event.invoke_box(space, self);
+        if let Some(ref invocation) = invocation {
+            for &mut (_, ref mut observer) in &mut self.observers {
+                observer.after(invocation, &result);
+            }
+        }
+
+        result.map_err(|error| Failure {
+            id: element.id,
+            execute_time: element.execute_time,
+            kind,
+            error,
+        })
+    }
+
+    pub fn simulate(
+        &mut self,
+        space: &mut entity_heap::EntityHeap,
+        until: units::Time
+    ) -> Vec<Failure> {
+        let mut failures = Vec::new();
+        while let Some(next_time) = self.next() {
+            if next_time <= until {
+                if let Err(failure) = self.invoke_next(space) {
+                    failures.push(failure);
+                }
+            } else {
+                break;
+            }
+        }
+        self.current_time = until;
+        failures
+    }
+
+    // like simulate, but gives up after max_events,
+    //   so that events which keep scheduling more events at the same time
+    //   can't hang the caller
+    pub fn simulate_bounded(
+        &mut self,
+        space: &mut entity_heap::EntityHeap,
+        until: units::Time,
+        max_events: usize,
+    ) -> Report {
+        let mut failures = Vec::new();
+        let mut invoked = 0;
+        while let Some(next_time) = self.next() {
+            if next_time > until {
+                break;
+            }
+            if invoked == max_events {
+                let progress = Progress::Interrupted;
+                return Report { progress, failures };
+            }
+            if let Err(failure) = self.invoke_next(space) {
+                failures.push(failure);
+            }
+            invoked += 1;
+        }
+        self.current_time = until;
+        let progress = Progress::Finished;
+        Report { progress, failures }
+    }
+
+    pub fn enqueue<E>(
+        &mut self,
+        event: E,
+        delay: units::Duration
+    ) -> Result<EventID, ScheduleError>
+        where E: 'static + Event
+    {
+        let execute_time = self.delayed(delay)?;
+        self.enqueue_at(event, execute_time)
+    }
+
+    pub fn enqueue_at<E>(
+        &mut self,
+        event: E,
+        execute_time: units::Time
+    ) -> Result<EventID, ScheduleError>
+        where E: 'static + Event
+    {
+        self.check_time(execute_time)?;
+
+        let call_back = CallBack::Once(Box::new(event));
+        Ok(self.schedule(execute_time, call_back))
+    }
+
+    // fires `event` after `delay`, and then every `interval` after that
+    // cancelling the returned id stops the whole series
+    pub fn enqueue_periodic<E>(
+        &mut self,
+        event: E,
+        delay: units::Duration,
+        interval: units::Duration,
+        repeat: Repeat,
+    ) -> Result<EventID, ScheduleError>
+        where E: 'static + Event + Clone
+    {
+        let first_time = self.delayed(delay)?;
+        self.enqueue_periodic_at(event, first_time, interval, repeat)
+    }
+
+    pub fn enqueue_periodic_at<E>(
+        &mut self,
+        event: E,
+        first_time: units::Time,
+        interval: units::Duration,
+        repeat: Repeat,
+    ) -> Result<EventID, ScheduleError>
+        where E: 'static + Event + Clone
+    {
+        self.check_time(first_time)?;
+        let fires = match repeat {
+            Repeat::Forever => true,
+            Repeat::Times(count) => count > 0,
+            Repeat::Until(end) => first_time <= end,
+        };
+        if interval <= 0 || !fires {
+            return Err(ScheduleError::BadPeriod { interval, repeat });
+        }
+
+        let event = Box::new(event);
+        let periodic = Periodic { event, interval, repeat };
+        let call_back = CallBack::Repeat(periodic);
+        Ok(self.schedule(first_time, call_back))
+    }
+
+    pub fn add_observer<O>(&mut self, observer: O) -> ObserverID
+        where O: 'static + Observer
+    {
+        let id = self.next_observer;
+        self.next_observer += 1;
+
+        self.observers.push((id, Box::new(observer)));
+        id
+    }
+
+    // returns false if there was no such observer
+    pub fn remove_observer(&mut self, id: ObserverID) -> bool {
+        let before = self.observers.len();
+        self.observers.retain(|&(other, _)| other != id);
+        self.observers.len() != before
+    }
+
+    pub fn is_pending(&self, id: EventID) -> bool {
+        self.pending.contains_key(&id)
+    }
+
+    // every pending event, in the order that they would run
+    pub fn pending_events<'a>(&'a self) -> vec::IntoIter<PendingEvent<'a>> {
+        let mut pending: Vec<_> = self.pending.iter().collect();
+        pending.sort_by_key(|&(_, p)| (p.execute_time, p.sequence));
+
+        let events: Vec<_> = pending
+            .into_iter()
+            .map(|(&id, p)| PendingEvent {
+                id,
+                execute_time: p.execute_time,
+                interval: p.call_back.interval(),
+                event: p.call_back.event(),
+            })
+            .collect();
+        events.into_iter()
+    }
+
+    // how many events of each kind are pending
+    pub fn kind_counts(&self) -> collections::HashMap<&'static str, usize> {
+        let mut counts = collections::HashMap::new();
+        for pending in self.pending.values() {
+            let kind = pending.call_back.event().kind();
+            *counts.entry(kind).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    // returns false if the event has already run or been cancelled
+    pub fn cancel(&mut self, id: EventID) -> bool {
+        let found = self.pending.remove(&id).is_some();
+        self.discard_stale();
+        found
+    }
+
+    // moves a pending event to `delay` after the current time
+    pub fn reschedule(
+        &mut self,
+        id: EventID,
+        delay: units::Duration
+    ) -> Result<(), ScheduleError> {
+        let execute_time = self.delayed(delay)?;
+        self.reschedule_at(id, execute_time)
+    }
+
+    pub fn reschedule_at(
+        &mut self,
+        id: EventID,
+        execute_time: units::Time
+    ) -> Result<(), ScheduleError> {
+        self.check_time(execute_time)?;
+        if !self.is_pending(id) {
+            return Err(ScheduleError::NotPending(id));
+        }
+
+        let sequence = self.take_sequence();
+        if let Some(pending) = self.pending.get_mut(&id) {
+            pending.execute_time = execute_time;
+            pending.sequence = sequence;
+        }
+        self.queue.push(QueueElement { execute_time, sequence, id });
+        self.discard_stale();
+        Ok(())
+    }
+
+    fn schedule(
+        &mut self,
+        execute_time: units::Time,
+        call_back: CallBack
+    ) -> EventID {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.insert(id, execute_time, call_back);
+        id
+    }
+
+    fn insert(
+        &mut self,
+        id: EventID,
+        execute_time: units::Time,
+        call_back: CallBack
+    ) {
+        let sequence = self.take_sequence();
+        let pending = Pending {
+            execute_time,
+            sequence,
+            call_back,
+        };
+        self.pending.insert(id, pending);
+        self.queue.push(QueueElement { execute_time, sequence, id });
+    }
+
+    // puts a periodic event back in the queue after it fired at `last_time`
+    // a series that would go on past the last time there is stops instead
+    fn repeat(
+        &mut self,
+        id: EventID,
+        last_time: units::Time,
+        periodic: Periodic
+    ) {
+        let execute_time = match last_time.checked_add(periodic.interval) {
+            Some(execute_time) => execute_time,
+            None => return,
+        };
+        let repeat = match periodic.repeat {
+            Repeat::Forever => Repeat::Forever,
+            Repeat::Times(count) if count > 1 => Repeat::Times(count - 1),
+            Repeat::Until(end) if execute_time <= end => Repeat::Until(end),
+            _ => return,
+        };
+        let periodic = Periodic { repeat, ..periodic };
+        self.insert(id, execute_time, CallBack::Repeat(periodic));
+    }
+
+    fn delayed(
+        &self,
+        delay: units::Duration
+    ) -> Result<units::Time, ScheduleError> {
+        let now = self.current_time;
+        now.checked_add(delay)
+            .ok_or(ScheduleError::OutOfRange { now, delay })
+    }
+
+    fn check_time(&self, requested: units::Time) -> Result<(), ScheduleError> {
+        let now = self.current_time;
+        if requested < now {
+            Err(ScheduleError::InPast { now, requested })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn take_sequence(&mut self) -> u64 {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        sequence
+    }
+
+    fn is_stale(&self, element: &QueueElement) -> bool {
+        match self.pending.get(&element.id) {
+            Some(pending) => pending.sequence != element.sequence,
+            None => true,
+        }
+    }
+
+    // keeps next() honest by never leaving a stale element on top
+    fn discard_stale(&mut self) {
+        loop {
+            let stale = match self.queue.peek() {
+                Some(next) => self.is_stale(next),
+                None => false,
+            };
+            if !stale {
+                break;
+            }
+            self.queue.pop();
+        }
+    }
+}
+
@@ -0,0 +1,162 @@
+use std::collections::binary_heap;
+use std::mem;
+
+use units;
+
+use super::Backend;
+use super::QueueElement;
+
+// each level of the wheel has 1 << SLOT_BITS slots,
+//   and each slot spans the whole of one rotation of the level below it
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const LEVELS: usize = 6;
+
+// a slot on the lowest level spans a MOMENT,
+//   so six levels cover 2^36 MOMENTs, which is 2^32 SECs or about 136 years
+const GRANULARITY: units::Time = units::MOMENT;
+
+fn bucket(time: units::Time) -> i64 {
+    time.div_euclid(GRANULARITY)
+}
+
+fn digit(bucket: i64, level: usize) -> usize {
+    let shift = SLOT_BITS * level as u32;
+    ((bucket >> shift) as usize) & (SLOTS - 1)
+}
+
+struct Level {
+    // bit n is set when slots[n] is not empty
+    occupied: u64,
+    slots: Vec<Vec<QueueElement>>,
+}
+
+impl Level {
+    fn new() -> Level {
+        let slots = (0..SLOTS).map(|_| Vec::new()).collect();
+        Level { occupied: 0, slots }
+    }
+
+    fn insert(&mut self, slot: usize, element: QueueElement) {
+        self.occupied |= 1 << slot;
+        self.slots[slot].push(element);
+    }
+
+    fn take(&mut self, slot: usize) -> Vec<QueueElement> {
+        self.occupied &= !(1 << slot);
+        mem::take(&mut self.slots[slot])
+    }
+
+    // the first occupied slot after `slot`
+    fn next_after(&self, slot: usize) -> Option<usize> {
+        if slot + 1 == SLOTS {
+            return None;
+        }
+        let later = self.occupied & (!0 << (slot + 1));
+        if later != 0 {
+            Some(later.trailing_zeros() as usize)
+        } else {
+            None
+        }
+    }
+}
+
+// a hierarchical timing wheel, bucketing elements by the MOMENT they run in
+// only the bucket at the cursor is kept sorted, in `ready`,
+//   everything later waits unsorted in the slot for its bucket,
+//   or in a coarser slot that gets redistributed once the cursor reaches it
+// whenever anything is stored, `ready` holds the earliest elements
+pub struct TimingWheel {
+    cursor: i64,
+    ready: binary_heap::BinaryHeap<QueueElement>,
+    levels: Vec<Level>,
+    // elements too far from the cursor to fit in any level
+    overflow: binary_heap::BinaryHeap<QueueElement>,
+    len: usize,
+}
+
+impl TimingWheel {
+    pub fn new() -> TimingWheel {
+        TimingWheel {
+            cursor: 0,
+            ready: binary_heap::BinaryHeap::new(),
+            levels: (0..LEVELS).map(|_| Level::new()).collect(),
+            overflow: binary_heap::BinaryHeap::new(),
+            len: 0,
+        }
+    }
+
+    fn place(&mut self, element: QueueElement) {
+        let bucket = bucket(element.execute_time);
+        if bucket <= self.cursor {
+            self.ready.push(element);
+            return;
+        }
+
+        // the highest digit that differs from the cursor decides the level,
+        //   since every lower digit will pass through zero before then
+        let differ = (bucket ^ self.cursor) as u64;
+        let highest_bit = 63 - differ.leading_zeros();
+        let level = (highest_bit / SLOT_BITS) as usize;
+        if level < LEVELS {
+            let slot = digit(bucket, level);
+            self.levels[level].insert(slot, element);
+        } else {
+            self.overflow.push(element);
+        }
+    }
+
+    // moves the cursor forward until `ready` has something in it
+    fn advance(&mut self) {
+        while self.ready.is_empty() {
+            let next = (0..LEVELS).filter_map(|level| {
+                let current = digit(self.cursor, level);
+                self.levels[level]
+                    .next_after(current)
+                    .map(|slot| (level, slot))
+            }).next();
+
+            let elements =
+                if let Some((level, slot)) = next {
+                    let shift = SLOT_BITS * level as u32;
+                    let span = shift + SLOT_BITS;
+                    let base = (self.cursor >> span) << span;
+                    self.cursor = base | (slot as i64) << shift;
+                    self.levels[level].take(slot)
+                } else if let Some(first) = self.overflow.peek() {
+                    // the wheel is empty, so start over around the overflow
+                    self.cursor = bucket(first.execute_time);
+                    mem::take(&mut self.overflow).into_vec()
+                } else {
+                    return;
+                };
+
+            for element in elements {
+                self.place(element);
+            }
+        }
+    }
+}
+
+impl Backend for TimingWheel {
+    fn push(&mut self, element: QueueElement) {
+        if self.len == 0 {
+            self.cursor = bucket(element.execute_time);
+        }
+        self.len += 1;
+        self.place(element);
+    }
+
+    fn peek(&self) -> Option<&QueueElement> {
+        self.ready.peek()
+    }
+
+    fn pop(&mut self) -> Option<QueueElement> {
+        let element = self.ready.pop();
+        if element.is_some() {
+            self.len -= 1;
+            self.advance();
+        }
+        element
+    }
+}
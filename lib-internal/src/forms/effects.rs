@@ -1,9 +1,11 @@
+use std::cell;
 use std::rc;
 
 use entity_heap;
 use event_queue;
 use units;
 use physics;
+use walls;
 
 
 pub trait Cast {
@@ -13,7 +15,8 @@ pub trait Cast {
         time: &mut event_queue::EventQueue,
         ref_frame: physics::Body,
         target: units::Position,
-    );
+        lineage: entity_heap::Lineage,
+    ) -> Result<(), event_queue::EventError>;
 }
 
 pub trait Effect {
@@ -33,8 +36,24 @@ pub struct Smoke {
     pub shape: Circle,
 }
 
+impl entity_heap::AsEntity for Smoke {
+    const KIND: &'static str = "Smoke";
+
+    fn body(&self) -> Option<&physics::Body> {
+        Some(&self.body)
+    }
+
+    fn body_mut(&mut self) -> Option<&mut physics::Body> {
+        Some(&mut self.body)
+    }
+
+    fn shape(&self) -> Option<&Circle> {
+        Some(&self.shape)
+    }
+}
+
 pub struct SmokeClearEvent {
-    target: entity_heap::UID
+    target: entity_heap::EntityRef<Smoke>
 }
 
 impl event_queue::Event for SmokeClearEvent {
@@ -42,12 +61,48 @@ impl event_queue::Event for SmokeClearEvent {
         self: Self,
         space: &mut entity_heap::EntityHeap,
         _time: &mut event_queue::EventQueue
-    ) {
-        let smoke: Smoke = space
-            .remove(&self.target)
-            .expect("SmokeClearEvent called on nonexistent entity")
-            .expect("Smoke for SmokeClearEvent");
+    ) -> Result<(), event_queue::EventError> {
+        let smoke = space.remove(self.target)?;
         drop(smoke);
+        Ok(())
+    }
+
+    fn kind(&self) -> &'static str {
+        "SmokeClearEvent"
+    }
+
+    fn describe(&self) -> String {
+        format!("clear smoke {}", self.target.uid())
+    }
+
+    fn entities(&self) -> Vec<entity_heap::UID> {
+        vec![self.target.uid()]
+    }
+}
+
+// spawns an entity along with the event that ends it,
+//   or neither of them if the event can't be scheduled,
+//   so that nothing is left lying around forever
+fn spawn_until<T, E, F>(
+    space: &mut entity_heap::EntityHeap,
+    time: &mut event_queue::EventQueue,
+    matter: T,
+    lineage: entity_heap::Lineage,
+    duration: units::Duration,
+    end: F,
+) -> Result<(entity_heap::EntityRef<T>, event_queue::EventID),
+            event_queue::EventError>
+    where T: entity_heap::AsEntity,
+          E: 'static + event_queue::Event,
+          F: FnOnce(entity_heap::EntityRef<T>) -> E,
+{
+    let uid = entity_heap::new_entity_from(space, matter, lineage);
+    match time.enqueue(end(uid), duration) {
+        Ok(id) => Ok((uid, id)),
+        Err(err) => {
+            space.remove_entity(uid.uid());
+            Err(err.into())
+        },
     }
 }
 
@@ -63,20 +118,22 @@ impl Cast for SmokeCast {
         time: &mut event_queue::EventQueue,
         ref_frame: physics::Body,
         _target: units::Position,
-    ) {
-        let body = physics::Body::new_frozen(ref_frame.position(time.now()));
+        lineage: entity_heap::Lineage,
+    ) -> Result<(), event_queue::EventError> {
+        let position = ref_frame.try_position(time.now())?;
+        let body = physics::Body::new_frozen(position);
         let shape = self.shape.clone();
         let smoke = Smoke { body, shape };
 
-        let uid = entity_heap::new_entity(space, smoke);
-
-        let target = uid;
-        let event = SmokeClearEvent { target };
-
-        time.enqueue(
-            event,
+        spawn_until(
+            space,
+            time,
+            smoke,
+            lineage,
             self.duration,
-        );
+            |target| SmokeClearEvent { target },
+        )?;
+        Ok(())
     }
 }
 
@@ -85,11 +142,28 @@ pub struct Bolt {
     pub body: physics::Body,
     pub shape: Circle,
     action: rc::Rc<Cast>,
+    next_bounce: walls::BounceTracker,
+}
+
+impl entity_heap::AsEntity for Bolt {
+    const KIND: &'static str = "Bolt";
+
+    fn body(&self) -> Option<&physics::Body> {
+        Some(&self.body)
+    }
+
+    fn body_mut(&mut self) -> Option<&mut physics::Body> {
+        Some(&mut self.body)
+    }
+
+    fn shape(&self) -> Option<&Circle> {
+        Some(&self.shape)
+    }
 }
 
 // deletes bolt, and casts action
 pub struct BoltLandEvent {
-    target: entity_heap::UID
+    target: entity_heap::EntityRef<Bolt>
 }
 
 impl event_queue::Event for BoltLandEvent {
@@ -97,19 +171,37 @@ impl event_queue::Event for BoltLandEvent {
         self: Self,
         space: &mut entity_heap::EntityHeap,
         time: &mut event_queue::EventQueue
-    ) {
-        let bolt: Bolt = space
-            .remove(&self.target)
-            .expect("BoltLandEvent called on nonexistent entity")
-            .expect("Bolt for BoltLandEvent");
-        let loc = bolt.body.position(time.now());
+    ) -> Result<(), event_queue::EventError> {
+        let uid = self.target.uid();
+        let lineage = space
+            .lineage(uid)
+            .map(|lineage| lineage.child_of(uid))
+            .ok_or(entity_heap::EntityError::MissingEntity(uid))?;
+        let bolt = space.remove(self.target)?;
+        if let Some(bounce) = bolt.next_bounce.get() {
+            time.cancel(bounce);
+        }
+        let loc = bolt.body.try_position(time.now())?;
 
         bolt.action.cast(
             space,
             time,
             bolt.body,
             loc,
-        );
+            lineage,
+        )
+    }
+
+    fn kind(&self) -> &'static str {
+        "BoltLandEvent"
+    }
+
+    fn describe(&self) -> String {
+        format!("land bolt {}", self.target.uid())
+    }
+
+    fn entities(&self) -> Vec<entity_heap::UID> {
+        vec![self.target.uid()]
     }
 }
 
@@ -127,26 +219,34 @@ impl Cast for BoltCast {
         time: &mut event_queue::EventQueue,
         ref_frame: physics::Body,
         target: units::Position,
-    ) {
-        let body = physics::Body::with_end_point(
-            ref_frame.position(time.now()),
-            target,
-            time.now(),
-            self.duration,
-        );
+        lineage: entity_heap::Lineage,
+    ) -> Result<(), event_queue::EventError> {
+        let now = time.now();
+        // like SmokeCast, fail rather than start from the edge of the world
+        let start = ref_frame.try_position(now)?;
+        target.checked_sub(start).ok_or(units::Overflow)?;
+        let body = ref_frame.split_to(target, now, self.duration);
         let shape = self.shape.clone();
         let action = rc::Rc::clone(&self.action);
-        let bolt = Bolt { body, shape, action };
+        let next_bounce = rc::Rc::new(cell::Cell::new(None));
+        let tracker = rc::Rc::clone(&next_bounce);
+        let bolt = Bolt { body, shape, action, next_bounce };
 
-        let uid = entity_heap::new_entity(space, bolt);
-
-        let target = uid;
-        let event = BoltLandEvent { target };
-
-        time.enqueue(
-            event,
+        let (uid, land) = spawn_until(
+            space,
+            time,
+            bolt,
+            lineage,
             self.duration,
-        );
+            |target| BoltLandEvent { target },
+        )?;
+        let bounce = walls::predict_tracked(space, time, uid.uid(), tracker);
+        if let Err(err) = bounce {
+            time.cancel(land);
+            space.remove_entity(uid.uid());
+            return Err(err);
+        }
+        Ok(())
     }
 }
 
@@ -161,15 +261,26 @@ impl Cast for ClusterCast {
         time: &mut event_queue::EventQueue,
         ref_frame: physics::Body,
         target: units::Position,
-    ) {
+        lineage: entity_heap::Lineage,
+    ) -> Result<(), event_queue::EventError> {
+        // the formation turns with whatever cast it
+        let heading = ref_frame.heading(time.now());
+        // one part failing doesn't stop the rest of the formation,
+        //   and the first failure is the one reported
+        let mut result = Ok(());
         for &(loc, ref action) in self.actions.iter() {
-            action.cast(
+            let cast = action.cast(
                 space,
                 time,
                 ref_frame.clone(),
-                target + loc,
+                target + loc.rotate(heading),
+                lineage.clone(),
             );
+            if result.is_ok() {
+                result = cast;
+            }
         }
+        result
     }
 }
 
@@ -2,5 +2,8 @@ pub mod units;
 pub mod physics;
 pub mod event_queue;
 pub mod entity_heap;
+pub mod spatial;
+pub mod collision;
+pub mod walls;
 pub mod forms;
 
@@ -1,22 +1,120 @@
+use std::cell;
+use std::cmp;
+use std::rc;
+
+use entity_heap;
 use units;
+use walls;
+
+// a body that others can be attached to, which can still be moved around
+//   with everything attached to it following along
+pub type Anchor = rc::Rc<cell::RefCell<Body>>;
 
+pub fn anchor(body: Body) -> Anchor {
+    rc::Rc::new(cell::RefCell::new(body))
+}
+
+// bodies compare equal when they describe the same motion,
+//   so a snapshot can be used to tell if the original has bounced since
+// attached bodies compare their anchors too, so moving an anchor
+//   counts as changing the motion of everything attached to it
+// a clone shares anchors with the original, and follows them as they move
 #[derive(Clone, Debug)]
 pub struct Body {
     last_position: units::Position,
-    current_velocity: units::Velocity,
+    last_velocity: units::Velocity,
+    // what rounding down to whole units left off the two above,
+    //   in 1 / (2 * SEC) of a unit and 1 / SEC of a unit per tick
+    position_remainder: units::Vec2,
+    velocity_remainder: units::Vec2,
+    acceleration: units::Acceleration,
     last_time: units::Time,
+    // when a body that is sliding to a halt stops dead
+    halt_time: Option<units::Time>,
+    // turning carries on regardless of how the body moves
+    last_heading: units::Angle,
+    spin: units::AngularVelocity,
+    // everything above is relative to the parent, if there is one,
+    //   moving and turning along with it
+    parent: Option<Anchor>,
+}
+
+impl PartialEq for Body {
+    fn eq(&self, other: &Body) -> bool {
+        let same_parent = match (&self.parent, &other.parent) {
+            (Some(first), Some(second)) => {
+                rc::Rc::ptr_eq(first, second)
+                    || *first.borrow() == *second.borrow()
+            },
+            (None, None) => true,
+            _ => false,
+        };
+        same_parent
+            && self.last_position == other.last_position
+            && self.last_velocity == other.last_velocity
+            && self.position_remainder == other.position_remainder
+            && self.velocity_remainder == other.velocity_remainder
+            && self.acceleration == other.acceleration
+            && self.last_time == other.last_time
+            && self.halt_time == other.halt_time
+            && self.last_heading == other.last_heading
+            && self.spin == other.spin
+    }
 }
 
+impl Eq for Body {}
+
 impl Body {
     pub fn new(
         position: units::Position,
         velocity: units::Velocity,
         time: units::Time
+    ) -> Body {
+        Body::new_accelerating(position, velocity, units::ZERO_VEC, time)
+    }
+
+    pub fn new_accelerating(
+        position: units::Position,
+        velocity: units::Velocity,
+        acceleration: units::Acceleration,
+        time: units::Time
     ) -> Body {
         Body {
             last_position: position,
-            current_velocity: velocity,
+            last_velocity: velocity,
+            position_remainder: units::ZERO_VEC,
+            velocity_remainder: units::ZERO_VEC,
+            acceleration,
             last_time: time,
+            halt_time: None,
+            last_heading: 0,
+            spin: 0,
+            parent: None,
+        }
+    }
+
+    // slows down evenly from `velocity` to a stop after `stop_time`,
+    //   and then stays where it stopped
+    pub fn new_sliding(
+        position: units::Position,
+        velocity: units::Velocity,
+        stop_time: units::Duration,
+        time: units::Time
+    ) -> Body {
+        if stop_time <= 0 {
+            return Body::new(position, units::ZERO_VEC, time);
+        }
+        Body {
+            last_position: position,
+            last_velocity: velocity,
+            position_remainder: units::ZERO_VEC,
+            velocity_remainder: units::ZERO_VEC,
+            acceleration: -(velocity * units::SEC) / stop_time,
+            last_time: time,
+            halt_time: Some(time + stop_time),
+            last_heading: 0,
+            spin: 0,
+            parent: None,
         }
     }
 
@@ -27,58 +125,555 @@ impl Body {
         travel_time: units::Duration,
     ) -> Body {
         if travel_time != 0 {
-            Body {
-                last_position: start,
-                current_velocity: (end - start) / travel_time,
-                last_time: start_time,
-            }
+            Body::new(start, (end - start) / travel_time, start_time)
         } else {
             Body::new_frozen(end)
         }
     }
 
     pub fn new_frozen(position: units::Position) -> Body {
-        Body {
-            last_position: position,
-            current_velocity: units::ZERO_VEC,
-            last_time: 0,
-        }
+        Body::new(position, units::ZERO_VEC, 0)
     }
 
+    // starts at `offset` from the parent, as seen facing its heading,
+    //   and moves away from there at `velocity`
+    pub fn new_attached(
+        parent: &Anchor,
+        offset: units::Displacement,
+        velocity: units::Velocity,
+        time: units::Time
+    ) -> Body {
+        let mut body = Body::new(offset, velocity, time);
+        body.parent = Some(rc::Rc::clone(parent));
+        body
+    }
+
+    pub fn parent(&self) -> Option<&Anchor> {
+        self.parent.as_ref()
+    }
+
+    // a copy with copies of every anchor, as they are now,
+    //   which stops comparing equal once any of the originals has moved
+    pub fn snapshot(&self) -> Body {
+        let mut body = self.clone();
+        body.parent = self.parent.as_ref().map(|parent| {
+            anchor(parent.borrow().snapshot())
+        });
+        body
+    }
+
+    // carries on from the same place in the same way,
+    //   but no longer follows the parent
+    pub fn detach(&mut self, now: units::Time) {
+        *self = match self.parent {
+            Some(_) => self.detached(self.velocity(now), now),
+            None => self.rebase(now),
+        };
+    }
+
+    // where the body is in the world, going through every parent
+    // a body that goes beyond the limits of the units stops at the edge,
+    //   so use try_position to find out when that has happened
     pub fn position(&self, now: units::Time) -> units::Position {
-        let dtime = now - self.last_time;
-        let displacement = self.current_velocity * dtime;
-        self.last_position + displacement
+        let local = self.local_position(now);
+        match self.parent {
+            Some(ref parent) => {
+                let parent = parent.borrow();
+                let offset = local.rotate(parent.heading(now));
+                parent.position(now).saturating_add(offset)
+            },
+            None => local,
+        }
+    }
+
+    pub fn try_position(
+        &self,
+        now: units::Time
+    ) -> Result<units::Position, units::Overflow> {
+        let (x, y) = self.wide_position(now);
+        let local = units::Vec2 {
+            x: units::checked_scalar(x)?,
+            y: units::checked_scalar(y)?,
+        };
+        match self.parent {
+            Some(ref parent) => {
+                let parent = parent.borrow();
+                let offset = local
+                    .checked_rotate(parent.heading(now))
+                    .ok_or(units::Overflow)?;
+                parent
+                    .try_position(now)?
+                    .checked_add(offset)
+                    .ok_or(units::Overflow)
+            },
+            None => Ok(local),
+        }
+    }
+
+    pub fn velocity(&self, now: units::Time) -> units::Velocity {
+        let local = self.local_velocity(now);
+        match self.parent {
+            Some(ref parent) => {
+                let parent = parent.borrow();
+                // being carried around as the parent turns
+                let offset = self.local_position(now);
+                let swing = swing(offset, parent.spin());
+                let relative = (local + swing).rotate(parent.heading(now));
+                parent.velocity(now) + relative
+            },
+            None => local,
+        }
+    }
+
+    // which way the body is facing, as an angle from 0 up to a TURN
+    pub fn heading(&self, now: units::Time) -> units::Angle {
+        let local = self.local_heading(now);
+        match self.parent {
+            Some(ref parent) => {
+                let heading = parent.borrow().heading(now) + local;
+                heading.rem_euclid(units::TURN)
+            },
+            None => local,
+        }
+    }
+
+    pub fn spin(&self) -> units::AngularVelocity {
+        match self.parent {
+            Some(ref parent) => parent.borrow().spin() + self.spin,
+            None => self.spin,
+        }
+    }
+
+    // the acceleration relative to the parent, as the world sees it,
+    //   with the parent's own added on
+    fn frame_acceleration(&self, now: units::Time) -> units::Acceleration {
+        match self.parent {
+            Some(ref parent) => {
+                let parent = parent.borrow();
+                let local = self.acceleration.rotate(parent.heading(now));
+                parent.frame_acceleration(now) + local
+            },
+            None => self.acceleration,
+        }
+    }
+
+    // no slower than the body goes at any time between the two
+    fn speed_bound(&self, from: units::Time, until: units::Time) -> f64 {
+        // speeding up or slowing down evenly,
+        //   it is fastest at one end or the other
+        // a body sliding to a halt stops dead from whatever speed is left
+        let speed = |time| {
+            let (x, y) = path_velocity(self, time);
+            x.hypot(y)
+        };
+        let until = self.halt_time.map_or(until, |halt| until.min(halt));
+        let local = speed(from.min(until)).max(speed(until)) + ROUNDING;
+        match self.parent {
+            Some(ref parent) => {
+                let parent = parent.borrow();
+                let (min, max) = self.local_bounds(from, until);
+                let rate = units::radians(parent.spin()).abs();
+                let swing = rate * extent(min, max) + ROUNDING;
+                parent.speed_bound(from, until) + local + swing
+            },
+            None => local,
+        }
+    }
+
+    fn local_position(&self, now: units::Time) -> units::Position {
+        let (x, y) = self.wide_position(now);
+        units::Vec2 {
+            x: units::saturating_scalar(x),
+            y: units::saturating_scalar(y),
+        }
+    }
+
+    fn wide_position(&self, now: units::Time) -> (i128, i128) {
+        let [(x, _), (y, _)] = self.exact_position(now);
+        (x, y)
+    }
+
+    // positions and velocities round down to the unit below,
+    //   and these give what was rounded off as well, so that a body
+    //   split at its current velocity carries on exactly as it was
+    // the working is done in wider integers, which can only saturate
+    //   when the duration itself is beyond the limits of Time
+    fn exact_position(&self, now: units::Time) -> [(i128, i128); 2] {
+        let now = self.halt_time.map_or(now, |halt| now.min(halt));
+        let dtime = now as i128 - self.last_time as i128;
+        let per_unit = 2 * units::SEC as i128;
+        let axis = |position: units::Scalar,
+                    velocity: units::Scalar,
+                    position_remainder: units::Scalar,
+                    velocity_remainder: units::Scalar,
+                    acceleration: units::Scalar| {
+            let drift = (acceleration as i128)
+                .saturating_mul(dtime)
+                .saturating_mul(dtime)
+                .saturating_add(2 * velocity_remainder as i128 * dtime)
+                .saturating_add(position_remainder as i128);
+            let displacement = (velocity as i128)
+                .saturating_mul(dtime)
+                .saturating_add(drift.div_euclid(per_unit));
+            let position = (position as i128).saturating_add(displacement);
+            (position, drift.rem_euclid(per_unit))
+        };
+        [
+            axis(
+                self.last_position.x,
+                self.last_velocity.x,
+                self.position_remainder.x,
+                self.velocity_remainder.x,
+                self.acceleration.x
+            ),
+            axis(
+                self.last_position.y,
+                self.last_velocity.y,
+                self.position_remainder.y,
+                self.velocity_remainder.y,
+                self.acceleration.y
+            ),
+        ]
+    }
+
+    fn exact_velocity(&self, now: units::Time) -> [(i128, i128); 2] {
+        if self.halted(now) {
+            return [(0, 0), (0, 0)];
+        }
+        let dtime = now as i128 - self.last_time as i128;
+        let per_unit = units::SEC as i128;
+        let axis = |velocity: units::Scalar,
+                    remainder: units::Scalar,
+                    acceleration: units::Scalar| {
+            let gained = (acceleration as i128)
+                .saturating_mul(dtime)
+                .saturating_add(remainder as i128);
+            let velocity = velocity as i128 + gained.div_euclid(per_unit);
+            (velocity, gained.rem_euclid(per_unit))
+        };
+        [
+            axis(
+                self.last_velocity.x,
+                self.velocity_remainder.x,
+                self.acceleration.x
+            ),
+            axis(
+                self.last_velocity.y,
+                self.velocity_remainder.y,
+                self.acceleration.y
+            ),
+        ]
+    }
+
+    fn local_velocity(&self, now: units::Time) -> units::Velocity {
+        let [(x, _), (y, _)] = self.exact_velocity(now);
+        units::Vec2 {
+            x: units::saturating_scalar(x),
+            y: units::saturating_scalar(y),
+        }
+    }
+
+    fn local_heading(&self, now: units::Time) -> units::Angle {
+        let dtime = now as i128 - self.last_time as i128;
+        let turned = self.spin as i128 * dtime;
+        let heading = self.last_heading as i128 + turned;
+        heading.rem_euclid(units::TURN as i128) as units::Angle
+    }
+
+    // relative to the parent, for attached bodies
+    pub fn acceleration(&self) -> units::Acceleration {
+        self.acceleration
+    }
+
+    pub fn halt_time(&self) -> Option<units::Time> {
+        self.halt_time
+    }
+
+    // for attached bodies, this is only about moving relative to the parent
+    pub fn halted(&self, now: units::Time) -> bool {
+        self.halt_time.is_some_and(|halt| now >= halt)
+    }
+
+    // the acceleration from `now` on, unless it is going to change
+    //   when the body halts
+    fn steady_acceleration(
+        &self,
+        now: units::Time
+    ) -> Option<units::Acceleration> {
+        if self.parent.is_some() {
+            return None;
+        }
+        match self.halt_time {
+            Some(halt) if now < halt => None,
+            Some(_) => Some(units::ZERO_VEC),
+            None => Some(self.acceleration),
+        }
+    }
+
+    // whether the body stays exactly where it is from `now` on,
+    //   facing the same way, and so does every parent
+    fn at_rest(&self, now: units::Time) -> bool {
+        let still = self.halted(now)
+            || (self.exact_velocity(now) == [(0, 0), (0, 0)]
+                && self.acceleration == units::ZERO_VEC);
+        still && self.spin == 0 && self.parent.as_ref().is_none_or(|parent| {
+            parent.borrow().at_rest(now)
+        })
+    }
+
+    // the last tick up to which each axis of the position keeps moving
+    //   the same way as it does at `now`, for bodies without a parent
+    // after this one axis turns around, at most once, before the body halts
+    fn turning_time(&self, now: units::Time) -> units::Time {
+        let mut turn = units::Time::MAX;
+        for vertex in self.vertices() {
+            let before_halt = self.halt_time
+                .is_none_or(|halt| vertex < halt as i128);
+            if vertex >= now as i128 && before_halt {
+                let vertex = vertex.min(units::Time::MAX as i128);
+                turn = turn.min(vertex as units::Time);
+            }
+        }
+        turn
     }
 
-    pub fn velocity(&self) -> units::Velocity {
-        self.current_velocity
+    // for each axis that accelerates, the tick at or just before
+    //   the velocity along it passes through zero
+    fn vertices(&self) -> Vec<i128> {
+        let axes = [
+            (
+                self.last_velocity.x,
+                self.velocity_remainder.x,
+                self.acceleration.x,
+            ),
+            (
+                self.last_velocity.y,
+                self.velocity_remainder.y,
+                self.acceleration.y,
+            ),
+        ];
+        axes.iter()
+            .filter(|&&(_, _, acceleration)| acceleration != 0)
+            .map(|&(velocity, remainder, acceleration)| {
+                let exact = velocity as i128 * units::SEC as i128
+                    + remainder as i128;
+                let dtime = (-exact).div_euclid(acceleration as i128);
+                self.last_time as i128 + dtime
+            })
+            .collect()
     }
 
+    // opposite corners of a box containing every position
+    //   that the body passes through between the two times
+    pub fn bounds(
+        &self,
+        from: units::Time,
+        until: units::Time
+    ) -> (units::Position, units::Position) {
+        let (min, max) = self.local_bounds(from, until);
+        let parent = match self.parent {
+            Some(ref parent) => parent.borrow(),
+            None => return (min, max),
+        };
+        let (parent_min, parent_max) = parent.bounds(from, until);
+        let (min, max) = if parent.spin() == 0 {
+            // the box just turns to face the same way as the parent
+            let heading = parent.heading(from);
+            let corners = [
+                min.rotate(heading),
+                max.rotate(heading),
+                units::Vec2 { x: min.x, y: max.y }.rotate(heading),
+                units::Vec2 { x: max.x, y: min.y }.rotate(heading),
+            ];
+            let mut turned_min = corners[0];
+            let mut turned_max = corners[0];
+            for corner in &corners[1..] {
+                turned_min.x = turned_min.x.min(corner.x);
+                turned_min.y = turned_min.y.min(corner.y);
+                turned_max.x = turned_max.x.max(corner.x);
+                turned_max.y = turned_max.y.max(corner.y);
+            }
+            (turned_min, turned_max)
+        } else {
+            // the box could be facing anywhere
+            let reach = extent(min, max).ceil() as units::Scalar;
+            let reach = units::Vec2 { x: reach, y: reach };
+            (-reach, reach)
+        };
+        (parent_min + min, parent_max + max)
+    }
+
+    fn local_bounds(
+        &self,
+        from: units::Time,
+        until: units::Time
+    ) -> (units::Position, units::Position) {
+        let start = self.local_position(from);
+        let end = self.local_position(until);
+        let mut min = units::Vec2 {
+            x: start.x.min(end.x),
+            y: start.y.min(end.y),
+        };
+        let mut max = units::Vec2 {
+            x: start.x.max(end.x),
+            y: start.y.max(end.y),
+        };
+        // an accelerating body can turn back along an axis,
+        //   in which case it goes furthest when that velocity is zero
+        for turn in self.vertices() {
+            let turn = turn
+                .max(units::Time::MIN as i128)
+                .min(units::Time::MAX as i128 - 1) as units::Time;
+            for &time in &[turn, turn + 1] {
+                if time > from && time < until {
+                    let position = self.local_position(time);
+                    min.x = min.x.min(position.x);
+                    min.y = min.y.min(position.y);
+                    max.x = max.x.max(position.x);
+                    max.y = max.y.max(position.y);
+                }
+            }
+        }
+        (min, max)
+    }
+
+    // the new body keeps accelerating the same way,
+    //   or if this one is sliding, still halts at the same time
+    // attached bodies stay attached, going at `velocity` in the world
+    //   at `now`, and from then on carried along by the parent as before
+    // splitting at the body's own velocity changes nothing about its path
     pub fn split(
         &self,
         velocity: units::Velocity,
         now: units::Time
     ) -> Body {
-        Body {
-            last_position: self.position(now),
-            current_velocity: velocity,
-            last_time: now,
+        if velocity == self.velocity(now) {
+            return self.rebase(now);
+        }
+        let velocity = match self.parent {
+            Some(ref parent) => {
+                let parent = parent.borrow();
+                let position = self.local_position(now);
+                let relative = (velocity - parent.velocity(now))
+                    .rotate(-parent.heading(now));
+                relative - swing(position, parent.spin())
+            },
+            None => velocity,
+        };
+        let mut body = self.rebase(now);
+        body.last_velocity = velocity;
+        body.velocity_remainder = units::ZERO_VEC;
+        if let Some(halt) = body.halt_time {
+            body.acceleration = body.sliding_acceleration(halt - now);
         }
+        body
+    }
+
+    // what slows the body evenly to a stop after `stop_time`,
+    //   from all of the velocity it has at `last_time`
+    fn sliding_acceleration(
+        &self,
+        stop_time: units::Duration
+    ) -> units::Acceleration {
+        let axis = |velocity: units::Scalar, remainder: units::Scalar| {
+            let exact = velocity as i128 * units::SEC as i128
+                + remainder as i128;
+            units::saturating_scalar(-exact / stop_time as i128)
+        };
+        units::Vec2 {
+            x: axis(self.last_velocity.x, self.velocity_remainder.x),
+            y: axis(self.last_velocity.y, self.velocity_remainder.y),
+        }
+    }
+
+    // the same motion, and facing the same way, but starting from `now`
+    // this is exact, keeping the fractions of a unit the body is at
+    fn rebase(&self, now: units::Time) -> Body {
+        let [(x, x_fraction), (y, y_fraction)] = self.exact_position(now);
+        let [(dx, dx_fraction), (dy, dy_fraction)] = self.exact_velocity(now);
+        let halted = self.halted(now);
+        let mut body = Body {
+            last_position: units::Vec2 {
+                x: units::saturating_scalar(x),
+                y: units::saturating_scalar(y),
+            },
+            last_velocity: units::Vec2 {
+                x: units::saturating_scalar(dx),
+                y: units::saturating_scalar(dy),
+            },
+            // remainders are always less than one unit
+            position_remainder: units::Vec2 {
+                x: x_fraction as units::Scalar,
+                y: y_fraction as units::Scalar,
+            },
+            velocity_remainder: units::Vec2 {
+                x: dx_fraction as units::Scalar,
+                y: dy_fraction as units::Scalar,
+            },
+            acceleration: if halted {
+                units::ZERO_VEC
+            } else {
+                self.acceleration
+            },
+            last_time: now,
+            halt_time: if halted { None } else { self.halt_time },
+            last_heading: 0,
+            spin: 0,
+            parent: self.parent.clone(),
+        };
+        body.turn(self.heading(now), self.spin(), now);
+        body
+    }
+
+    // where the body is in the world at `now`, without a parent,
+    //   going at `velocity` and accelerating as it seems to
+    // this loses whatever the parent's rounding has left off
+    fn detached(&self, velocity: units::Velocity, now: units::Time) -> Body {
+        let position = self.position(now);
+        let mut body = match self.halt_time {
+            Some(halt) if now < halt => {
+                Body::new_sliding(position, velocity, halt - now, now)
+            },
+            Some(_) => Body::new(position, velocity, now),
+            None => Body::new_accelerating(
+                position,
+                velocity,
+                self.frame_acceleration(now),
+                now
+            ),
+        };
+        body.turn(self.heading(now), self.spin(), now);
+        body
     }
 
+    pub fn split_turning(
+        &self,
+        velocity: units::Velocity,
+        heading: units::Angle,
+        spin: units::AngularVelocity,
+        now: units::Time
+    ) -> Body {
+        let mut body = self.split(velocity, now);
+        body.turn(heading, spin, now);
+        body
+    }
+
+    // the new body moves in a straight line to the end point,
+    //   so attached bodies are left without a parent
     pub fn split_to(
         &self,
         end_point: units::Vec2,
         now: units::Time,
         end_time: units::Time
     ) -> Body {
-        Body::with_end_point(
+        let mut body = Body::with_end_point(
             self.position(now),
             end_point,
             now,
             end_time
-        )
+        );
+        body.turn(self.heading(now), self.spin(), now);
+        body
     }
 
     pub fn bounce(
@@ -89,6 +684,16 @@ impl Body {
         *self = self.split(velocity, now);
     }
 
+    pub fn bounce_turning(
+        &mut self,
+        velocity: units::Velocity,
+        heading: units::Angle,
+        spin: units::AngularVelocity,
+        now: units::Time
+    ) {
+        *self = self.split_turning(velocity, heading, spin, now);
+    }
+
     pub fn bounce_to(
         &mut self,
         end_point: units::Vec2,
@@ -98,10 +703,977 @@ impl Body {
         *self = self.split_to(end_point, now, end_time);
     }
 
+    // keeps the current velocity, and starts accelerating differently
+    // for attached bodies, this is relative to the parent
+    pub fn accelerate(
+        &mut self,
+        acceleration: units::Acceleration,
+        now: units::Time
+    ) {
+        let mut body = self.rebase(now);
+        body.acceleration = acceleration;
+        body.halt_time = None;
+        *self = body;
+    }
+
+    // starts sliding to a halt from the current velocity
+    // for attached bodies, this is relative to the parent
+    pub fn slide(&mut self, stop_time: units::Duration, now: units::Time) {
+        let mut body = self.rebase(now);
+        if stop_time <= 0 {
+            body.last_velocity = units::ZERO_VEC;
+            body.velocity_remainder = units::ZERO_VEC;
+            body.acceleration = units::ZERO_VEC;
+            body.halt_time = None;
+        } else {
+            body.acceleration = body.sliding_acceleration(stop_time);
+            body.halt_time = Some(now + stop_time);
+        }
+        *self = body;
+    }
+
+    // faces `heading` at `now`, turning at `spin` from then on,
+    //   without changing how the body moves
+    pub fn turn(
+        &mut self,
+        heading: units::Angle,
+        spin: units::AngularVelocity,
+        now: units::Time
+    ) {
+        let (heading, spin) = match self.parent {
+            Some(ref parent) => {
+                let parent = parent.borrow();
+                (heading - parent.heading(now), spin - parent.spin())
+            },
+            None => (heading, spin),
+        };
+        let dtime = now as i128 - self.last_time as i128;
+        let turned = spin as i128 * dtime;
+        let last_heading = heading as i128 - turned;
+        self.last_heading =
+            last_heading.rem_euclid(units::TURN as i128) as units::Angle;
+        self.spin = spin;
+    }
+
+    // stops moving and turning, still facing the same way,
+    //   and no longer follows the parent
     pub fn freeze(&mut self, now: units::Time) {
-        self.bounce(units::Vec2 { x: 0, y: 0 }, now);
+        let heading = self.heading(now);
+        *self = Body::new(self.position(now), units::ZERO_VEC, now);
+        self.turn(heading, 0, now);
+    }
+}
+
+
+// how fast a point at `offset` is carried around by a parent turning
+//   at `spin`, as seen facing the parent's heading
+fn swing(
+    offset: units::Displacement,
+    spin: units::AngularVelocity
+) -> units::Velocity {
+    let rate = units::radians(spin);
+    units::Vec2 {
+        x: (-offset.y as f64 * rate).round() as units::Scalar,
+        y: (offset.x as f64 * rate).round() as units::Scalar,
+    }
+}
+
+// how long a body sliding to a halt from this velocity
+//   should take in order to stop after going `distance`
+pub fn stopping_time(
+    velocity: units::Velocity,
+    distance: units::Scalar
+) -> units::Duration {
+    let speed = (length_sq(velocity) as f64).sqrt();
+    if speed == 0.0 {
+        return 0;
+    }
+    // it goes at half its starting speed on average
+    (2.0 * distance as f64 / speed).round() as units::Duration
+}
+
+
+// the first time from `from` onwards at which two circles
+//   following these bodies touch or overlap while moving closer
+// this is exact, in that the circles touch at the returned time,
+//   and don't at any earlier time that the units can express
+// circles that are already touching but moving apart are followed
+//   until they have separated, like in wall_contact_time
+// bodies that accelerate differently are only followed
+//   as far as SEARCH_HORIZON past `from`, and if they haven't touched
+//   by then this says when to look again
+pub fn contact_time(
+    first: &Body,
+    first_radius: units::Scalar,
+    second: &Body,
+    second_radius: units::Scalar,
+    from: units::Time,
+) -> Contact {
+    let reach = first_radius as i128 + second_radius as i128;
+    let reach_sq = reach * reach;
+    let touching = |time: units::Time| {
+        let offset = first.position(time) - second.position(time);
+        length_sq(offset) <= reach_sq
+    };
+
+    let mut from = from;
+    if touching(from) {
+        let offset = first.position(from) - second.position(from);
+        let velocity = first.velocity(from) - second.velocity(from);
+        if dot(offset, velocity) < 0 {
+            return Contact::At(from);
+        }
+        from = match leave_time(from, &touching) {
+            Some(leave) => leave,
+            None => return Contact::RecheckAt(from + SEARCH_HORIZON),
+        };
+    }
+    let first_acceleration = first.steady_acceleration(from);
+    if first_acceleration.is_none()
+        || first_acceleration != second.steady_acceleration(from)
+    {
+        return approach(first, second, reach, from);
+    }
+
+    // with the same acceleration, the first body moves in a straight line
+    //   relative to the second, so after `dtime` the distance between
+    //   them squared is a * dtime^2 + b * dtime + c
+    let start = first.position(from) - second.position(from);
+    let (first_x, first_y) = estimate_velocity(first, from);
+    let (second_x, second_y) = estimate_velocity(second, from);
+    let velocity = (first_x - second_x, first_y - second_y);
+    let a = velocity.0 * velocity.0 + velocity.1 * velocity.1;
+    let half_b = start.x as f64 * velocity.0 + start.y as f64 * velocity.1;
+    if a == 0.0 || half_b >= 0.0 {
+        // not moving closer together
+        return Contact::Never;
+    }
+    let c = (length_sq(start) - reach_sq) as f64;
+    let quarter_disc = half_b * half_b - a * c;
+    if quarter_disc < 0.0 {
+        return Contact::Never;
+    }
+
+    // estimate where the roots are, then settle on exact ticks
+    let root = quarter_disc.sqrt();
+    let enter = ((-half_b - root) / a).ceil() as units::Duration;
+    let leave = ((-half_b + root) / a).floor() as units::Duration;
+    let mut dtime = enter.max(1);
+    while dtime > 1 && touching(from + dtime - 1) {
+        dtime -= 1;
+    }
+    // the circles may only graze between two ticks, and never touch on one
+    while !touching(from + dtime) {
+        if dtime > leave + 1 {
+            return Contact::Never;
+        }
+        dtime += 1;
+    }
+    Contact::At(from + dtime)
+}
+
+// how far from the origin a box reaches
+fn extent(min: units::Vec2, max: units::Vec2) -> f64 {
+    let x = min.x.abs().max(max.x.abs()) as f64;
+    let y = min.y.abs().max(max.y.abs()) as f64;
+    x.hypot(y)
+}
+
+// the velocity without rounding, which the rounded positions follow
+//   more closely over long stretches than the rounded velocity does
+// for attached bodies, this is relative to the parent
+fn estimate_velocity(body: &Body, now: units::Time) -> (f64, f64) {
+    if body.halted(now) {
+        return (0.0, 0.0);
+    }
+    path_velocity(body, now)
+}
+
+// the same, as if the body were never going to halt
+fn path_velocity(body: &Body, now: units::Time) -> (f64, f64) {
+    let dtime = (now - body.last_time) as f64 / units::SEC as f64;
+    let axis = |
+        velocity: units::Scalar,
+        remainder: units::Scalar,
+        acceleration: units::Scalar
+    | {
+        velocity as f64
+            + remainder as f64 / units::SEC as f64
+            + acceleration as f64 * dtime
+    };
+    (
+        axis(
+            body.last_velocity.x,
+            body.velocity_remainder.x,
+            body.acceleration.x
+        ),
+        axis(
+            body.last_velocity.y,
+            body.velocity_remainder.y,
+            body.acceleration.y
+        ),
+    )
+}
+
+// how much speed a body sliding to a halt will lose all at once,
+//   if it has yet to stop
+fn halting_speed(body: &Body, now: units::Time) -> f64 {
+    match body.halt_time {
+        Some(halt) if now < halt => {
+            let (x, y) = path_velocity(body, halt);
+            x.hypot(y)
+        },
+        _ => 0.0,
+    }
+}
+
+pub const SEARCH_HORIZON: units::Duration = 64 * units::SEC;
+
+// what looking for the first time that two things touch found
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Contact {
+    At(units::Time),
+    // they don't touch before this, which is as far as the search went,
+    //   so unless either changes how it moves, look again from then
+    RecheckAt(units::Time),
+    Never,
+}
+
+impl PartialOrd for Contact {
+    fn partial_cmp(
+        self: &Contact,
+        other: &Contact
+    ) -> Option<cmp::Ordering> {
+        Some(Ord::cmp(self, other))
+    }
+}
+
+// sooner is less, and touching comes before looking again at the same time
+impl Ord for Contact {
+    fn cmp(
+        self: &Contact,
+        other: &Contact
+    ) -> cmp::Ordering {
+        let key = |contact: &Contact| match *contact {
+            Contact::At(time) => (time, 0),
+            Contact::RecheckAt(time) => (time, 1),
+            Contact::Never => (units::Time::MAX, 2),
+        };
+        Ord::cmp(&key(self), &key(other))
+    }
+}
+
+// how far rounding can put a body from its exact path,
+//   in position and in velocity
+const ROUNDING: f64 = 2.0;
+
+// steps forward by as much as the gap between the circles allows,
+//   given how fast it can close, so no tick where they touch is skipped
+fn approach(
+    first: &Body,
+    second: &Body,
+    reach: i128,
+    from: units::Time,
+) -> Contact {
+    if first.parent.is_some() || second.parent.is_some() {
+        let distance = |time: units::Time| {
+            let offset = first.position(time) - second.position(time);
+            (length_sq(offset) as f64).sqrt() - reach as f64
+        };
+        let closing = |from: units::Time, until: units::Time| {
+            first.speed_bound(from, until) + second.speed_bound(from, until)
+        };
+        let touching = |time: units::Time| {
+            let offset = first.position(time) - second.position(time);
+            length_sq(offset) <= reach * reach
+        };
+        let still = |time: units::Time| {
+            first.at_rest(time) && second.at_rest(time)
+        };
+        return advance(from, distance, closing, touching, still);
+    }
+
+    // closing speed gained per tick,
+    //   which is no more than when they accelerate in opposite directions
+    let magnitude = |body: &Body| {
+        (length_sq(body.acceleration) as f64).sqrt()
+    };
+    let acceleration = (magnitude(first) + magnitude(second))
+        / units::SEC as f64;
+    let mut time = from;
+    while time - from <= SEARCH_HORIZON {
+        let offset = first.position(time) - second.position(time);
+        let distance = (length_sq(offset) as f64).sqrt();
+        // rounding can put each body off its path, now and after the step
+        let gap = distance - reach as f64 - 4.0 * ROUNDING;
+        let step = if gap > 0.0 {
+            let (first_x, first_y) = estimate_velocity(first, time);
+            let (second_x, second_y) = estimate_velocity(second, time);
+            let speed = (first_x - second_x).hypot(first_y - second_y)
+                + halting_speed(first, time)
+                + halting_speed(second, time);
+            // along their paths, the gap can shrink by at most
+            //   speed * dtime + acceleration * dtime^2 / 2
+            let disc = speed * speed + 2.0 * acceleration * gap;
+            let dtime = if acceleration > 0.0 {
+                (disc.sqrt() - speed) / acceleration
+            } else {
+                gap / speed
+            };
+            let dtime = dtime.min(SEARCH_HORIZON as f64 + 1.0);
+            (dtime.floor() as units::Duration).max(1)
+        } else if length_sq(offset) <= reach * reach {
+            return Contact::At(time);
+        } else {
+            // too close to go by the gap, but nothing can change
+            //   until one of them moves to another unit
+            next_move(first, second, time, from + SEARCH_HORIZON) - time
+        };
+        time += step;
+    }
+    Contact::RecheckAt(time)
+}
+
+// the first tick after `from` at which either body without a parent
+//   is somewhere else, or `until` if neither has moved by then
+// positions change a whole unit at a time, and up to a turning point
+//   every change is further the same way along each axis,
+//   so a body back where it started has stayed there all along
+fn next_move(
+    first: &Body,
+    second: &Body,
+    from: units::Time,
+    until: units::Time,
+) -> units::Time {
+    let start = (first.position(from), second.position(from));
+    let moved = |time: units::Time| {
+        (first.position(time), second.position(time)) != start
+    };
+    let until = until
+        .min(first.turning_time(from))
+        .min(second.turning_time(from))
+        .max(from + 1);
+
+    // gallop ahead until something moves, then narrow down on when
+    let mut still = from;
+    let mut step = 1;
+    let mut moving = from + step;
+    while !moved(moving) {
+        if moving >= until {
+            return until;
+        }
+        still = moving;
+        step *= 2;
+        moving = (from + step).min(until);
+    }
+    while moving - still > 1 {
+        let middle = still + (moving - still) / 2;
+        if moved(middle) {
+            moving = middle;
+        } else {
+            still = middle;
+        }
+    }
+    moving
+}
+
+// steps forward by as much as the gap allows, given a bound on how fast
+//   it can close between two times, so no tick where it closes is skipped
+// this is slower than working the times out, but works for any motion
+// rounding while turning means positions can jitter back and forth,
+//   so when the gap is too small to go by this checks every tick,
+//   unless `still` says nothing moves from then on
+fn advance<G, C, T, S>(
+    from: units::Time,
+    gap: G,
+    closing: C,
+    touching: T,
+    still: S,
+) -> Contact
+    where G: Fn(units::Time) -> f64,
+          C: Fn(units::Time, units::Time) -> f64,
+          T: Fn(units::Time) -> bool,
+          S: Fn(units::Time) -> bool,
+{
+    let mut time = from;
+    while time - from <= SEARCH_HORIZON {
+        // attached bodies are rounded once for every parent
+        let gap = gap(time) - 4.0 * ROUNDING;
+        if gap <= 0.0 && touching(time) {
+            return Contact::At(time);
+        }
+        if still(time) {
+            return Contact::Never;
+        }
+        let step = if gap > 0.0 {
+            // guess from how fast it is closing now,
+            //   then back off until the bound covers the whole step
+            let guess = gap / closing(time, time).max(1.0);
+            let guess = guess.min(SEARCH_HORIZON as f64);
+            let mut step = (guess as units::Duration).max(1);
+            while step > 1 && closing(time, time + step) * step as f64 > gap {
+                step /= 2;
+            }
+            step
+        } else {
+            1
+        };
+        time += step;
+    }
+    Contact::RecheckAt(time)
+}
+
+// the first time from `from` onwards at which a circle following this body
+//   touches the line segment from `start` to `end` while moving towards it
+// a circle that is already moving away is followed until it has left,
+//   which is as far as SEARCH_HORIZON for one sliding along the segment
+// like contact_time, this can say to look again later instead
+pub fn wall_contact_time(
+    body: &Body,
+    radius: units::Scalar,
+    start: units::Position,
+    end: units::Position,
+    from: units::Time,
+) -> Contact {
+    let touching = |time: units::Time| {
+        segment_touches(body.position(time), radius, start, end)
+    };
+
+    let mut from = from;
+    if touching(from) {
+        let normal = wall_normal(body.position(from), start, end);
+        if dot(body.velocity(from), normal) < 0 {
+            return Contact::At(from);
+        }
+        from = match leave_time(from, &touching) {
+            Some(leave) => leave,
+            None => return Contact::RecheckAt(from + SEARCH_HORIZON),
+        };
+    }
+
+    if body.parent.is_some() {
+        let gap = |time: units::Time| {
+            let position = body.position(time);
+            segment_distance(position, start, end) - radius as f64
+        };
+        let closing = |from, until| body.speed_bound(from, until);
+        let still = |time| body.at_rest(time);
+        return advance(from, gap, closing, touching, still);
+    }
+
+    let face = face_contact_time(body, radius, start, end, from, &touching)
+        .map_or(Contact::Never, Contact::At);
+    [start, end].iter()
+        .map(|&corner| {
+            let corner = Body::new_frozen(corner);
+            contact_time(body, radius, &corner, 0, from)
+        })
+        .fold(face, cmp::min)
+}
+
+// the first tick after `from` at which the circle is no longer touching
+fn leave_time<F>(from: units::Time, touching: &F) -> Option<units::Time>
+    where F: Fn(units::Time) -> bool
+{
+    // gallop ahead until clear, then narrow down on the boundary
+    let mut inside = from;
+    let mut step = 1;
+    let mut outside = from + step;
+    while touching(outside) {
+        if outside - from > SEARCH_HORIZON {
+            return None;
+        }
+        inside = outside;
+        step *= 2;
+        outside = from + step;
+    }
+    while outside - inside > 1 {
+        let middle = inside + (outside - inside) / 2;
+        if touching(middle) {
+            inside = middle;
+        } else {
+            outside = middle;
+        }
+    }
+    Some(outside)
+}
+
+// when the circle first crosses into the band either side of the segment,
+//   at a point alongside it rather than past either end
+fn face_contact_time<F>(
+    body: &Body,
+    radius: units::Scalar,
+    start: units::Position,
+    end: units::Position,
+    from: units::Time,
+    touching: &F,
+) -> Option<units::Time>
+    where F: Fn(units::Time) -> bool
+{
+    let along = end - start;
+    if along == units::ZERO_VEC {
+        return None;
+    }
+    // the distance from the line through the segment,
+    //   scaled by its length, is a * dtime^2 + b * dtime + c
+    if body.halted(from) {
+        return None;
+    }
+    // a sliding body won't cross anything after it halts
+    let latest = body.halt_time.map_or(i64::MAX as f64 / 2.0, |halt| {
+        (halt - from) as f64 + 1.0
+    });
+    let offset = body.position(from) - start;
+    let a = cross(body.acceleration, along) as f64
+        / (2 * units::SEC) as f64;
+    let (velocity_x, velocity_y) = estimate_velocity(body, from);
+    let b = velocity_x * along.y as f64 - velocity_y * along.x as f64;
+    let c = cross(offset, along) as f64;
+    let reach = radius as f64 * (length_sq(along) as f64).sqrt();
+
+    let mut crossings = Vec::new();
+    for &side in &[reach, -reach] {
+        crossings.extend(roots(a, b, c - side));
+    }
+    crossings.retain(|&dtime| dtime >= 0.0 && dtime <= latest);
+    crossings.sort_by(|first, second| {
+        first.partial_cmp(second).expect("roots are never NaN")
+    });
+
+    // settle each estimate on the nearby ticks
+    for dtime in crossings {
+        let dtime = dtime.ceil() as units::Duration;
+        for tick in (dtime - 1).max(1)..dtime + 2 {
+            if touching(from + tick) {
+                return Some(from + tick);
+            }
+        }
+    }
+    None
+}
+
+// the real solutions of a * x^2 + b * x + c = 0
+fn roots(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a == 0.0 {
+        if b == 0.0 {
+            return Vec::new();
+        }
+        return vec![-c / b];
+    }
+    let disc = b * b - 4.0 * a * c;
+    if disc < 0.0 {
+        return Vec::new();
+    }
+    let root = disc.sqrt();
+    vec![(-b - root) / (2.0 * a), (-b + root) / (2.0 * a)]
+}
+
+// whether a circle at `center` touches the segment from `start` to `end`
+pub fn segment_touches(
+    center: units::Position,
+    radius: units::Scalar,
+    start: units::Position,
+    end: units::Position,
+) -> bool {
+    let reach_sq = radius as i128 * radius as i128;
+    let along = end - start;
+    let offset = center - start;
+    let projection = dot(offset, along);
+    let length = length_sq(along);
+    if projection <= 0 {
+        length_sq(offset) <= reach_sq
+    } else if projection >= length {
+        length_sq(center - end) <= reach_sq
+    } else {
+        let cross = cross(offset, along);
+        cross * cross <= reach_sq * length
+    }
+}
+
+fn segment_distance(
+    point: units::Position,
+    start: units::Position,
+    end: units::Position,
+) -> f64 {
+    let along = end - start;
+    let offset = point - start;
+    let projection = dot(offset, along);
+    let length = length_sq(along);
+    if projection <= 0 {
+        (length_sq(offset) as f64).sqrt()
+    } else if projection >= length {
+        (length_sq(point - end) as f64).sqrt()
+    } else {
+        cross(offset, along).abs() as f64 / (length as f64).sqrt()
+    }
+}
+
+// the direction from the nearest point of the segment out to `point`,
+//   at no particular scale
+// points on the segment itself get one of the segment's two normals
+pub fn wall_normal(
+    point: units::Position,
+    start: units::Position,
+    end: units::Position,
+) -> units::Vec2 {
+    let along = end - start;
+    let offset = point - start;
+    let projection = dot(offset, along);
+    let length = length_sq(along);
+    let normal = if projection <= 0 {
+        offset
+    } else if projection >= length {
+        point - end
+    } else {
+        let nearest = units::Vec2 {
+            x: (along.x as i128 * projection / length) as units::Scalar,
+            y: (along.y as i128 * projection / length) as units::Scalar,
+        };
+        offset - nearest
+    };
+    if normal == units::ZERO_VEC {
+        units::Vec2 { x: -along.y, y: along.x }
+    } else {
+        normal
+    }
+}
+
+// the velocity after bouncing off a surface facing along `normal`
+// velocities already heading away from the surface are kept as they are
+pub fn reflect(
+    velocity: units::Velocity,
+    normal: units::Vec2
+) -> units::Velocity {
+    let into = dot(velocity, normal);
+    let scale = length_sq(normal);
+    if into >= 0 || scale == 0 {
+        return velocity;
+    }
+    let axis = |velocity: units::Scalar, normal: units::Scalar| {
+        let change = 2 * into * normal as i128 / scale;
+        velocity - change as units::Scalar
+    };
+    units::Vec2 {
+        x: axis(velocity.x, normal.x),
+        y: axis(velocity.y, normal.y),
     }
 }
 
+// how far along a ray something is,
+//   counting up from 0 at the start to RAY_END at the end
+pub type RayParameter = i64;
+pub const RAY_END: RayParameter = 1 << 16;
+
+// the first thing a ray reaches
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RayHit {
+    pub uid: entity_heap::UID,
+    pub point: units::Position,
+    pub parameter: RayParameter,
+}
 
+// the point `parameter` of the way from `start` to `end`,
+//   rounding down like positions do
+pub fn ray_point(
+    start: units::Position,
+    end: units::Position,
+    parameter: RayParameter,
+) -> units::Position {
+    let axis = |start: units::Scalar, end: units::Scalar| {
+        let along = (end - start) as i128 * parameter as i128;
+        start + along.div_euclid(RAY_END as i128) as units::Scalar
+    };
+    units::Vec2 {
+        x: axis(start.x, end.x),
+        y: axis(start.y, end.y),
+    }
+}
 
+// where a ray from `start` to `end` first enters a circle,
+//   or 0 if it starts inside
+// like contact_time, the point at the returned parameter is in the circle,
+//   and the point at any earlier parameter isn't
+pub fn ray_circle(
+    start: units::Position,
+    end: units::Position,
+    center: units::Position,
+    radius: units::Scalar,
+) -> Option<RayParameter> {
+    let reach_sq = radius as i128 * radius as i128;
+    let inside = |parameter: RayParameter| {
+        let offset = ray_point(start, end, parameter) - center;
+        length_sq(offset) <= reach_sq
+    };
+
+    if inside(0) {
+        return Some(0);
+    }
+    // the distance squared from the center is
+    //   a * t^2 + 2 * half_b * t + c, for t going from 0 to 1
+    let along = end - start;
+    let offset = start - center;
+    let a = length_sq(along) as f64;
+    let half_b = dot(offset, along) as f64;
+    if a == 0.0 || half_b >= 0.0 {
+        return None;
+    }
+    let c = (length_sq(offset) - reach_sq) as f64;
+    let quarter_disc = half_b * half_b - a * c;
+    if quarter_disc < 0.0 {
+        return None;
+    }
+
+    // estimate where the ray goes in and out, then settle on exact values
+    let root = quarter_disc.sqrt();
+    let scale = RAY_END as f64;
+    let enter = ((-half_b - root) / a * scale).ceil() as RayParameter;
+    let leave = ((-half_b + root) / a * scale).floor() as RayParameter;
+    if enter > RAY_END {
+        return None;
+    }
+    let mut parameter = enter.max(1);
+    while parameter > 1 && inside(parameter - 1) {
+        parameter -= 1;
+    }
+    while !inside(parameter) {
+        if parameter > leave.min(RAY_END - 1) {
+            return None;
+        }
+        parameter += 1;
+    }
+    Some(parameter)
+}
+
+// where a ray from `start` to `end` first meets the segment
+//   from `wall_start` to `wall_end`, rounded up so that the ray has
+//   reached the segment by then
+pub fn ray_segment(
+    start: units::Position,
+    end: units::Position,
+    wall_start: units::Position,
+    wall_end: units::Position,
+) -> Option<RayParameter> {
+    let along = end - start;
+    let wall = wall_end - wall_start;
+    let offset = wall_start - start;
+    let ray_end = RAY_END as i128;
+
+    let denominator = cross(along, wall);
+    if denominator == 0 {
+        // parallel, so they only meet if they lie on the same line
+        if cross(offset, along) != 0 {
+            return None;
+        }
+        let length = length_sq(along);
+        if length == 0 {
+            let on_wall = segment_touches(start, 0, wall_start, wall_end);
+            return if on_wall { Some(0) } else { None };
+        }
+        let first = dot(offset, along);
+        let second = dot(wall_end - start, along);
+        let (near, far) = (first.min(second), first.max(second));
+        if far < 0 || near > length {
+            return None;
+        }
+        let parameter = div_ceil(near.max(0) * ray_end, length);
+        return Some(parameter as RayParameter);
+    }
+
+    // how far along each of them the lines cross,
+    //   as fractions over the same denominator
+    let (mut ray, mut across, mut denominator) = (
+        cross(offset, wall),
+        cross(offset, along),
+        denominator,
+    );
+    if denominator < 0 {
+        ray = -ray;
+        across = -across;
+        denominator = -denominator;
+    }
+    let within = |numerator: i128| {
+        numerator >= 0 && numerator <= denominator
+    };
+    if !within(ray) || !within(across) {
+        return None;
+    }
+    Some(div_ceil(ray * ray_end, denominator) as RayParameter)
+}
+
+// for a non-negative numerator and a positive denominator
+fn div_ceil(numerator: i128, denominator: i128) -> i128 {
+    (numerator + denominator - 1) / denominator
+}
+
+// the first entity that a ray from `start` to `end` reaches at `now`,
+//   out of those that `filter` accepts
+// walls are hit as segments, and anything with a body and a shape
+//   as a circle the size of that shape
+pub fn cast_ray<F>(
+    space: &entity_heap::EntityHeap,
+    now: units::Time,
+    start: units::Position,
+    end: units::Position,
+    mut filter: F,
+) -> Option<RayHit>
+    where F: FnMut(entity_heap::UID, &entity_heap::Entity) -> bool
+{
+    let mut first: Option<(RayParameter, entity_heap::UID)> = None;
+    for (&uid, ent) in space.iter() {
+        if !filter(uid, ent) {
+            continue;
+        }
+        let hit = if let Some(wall) = ent.downcast_ref::<walls::Wall>() {
+            ray_segment(start, end, wall.start, wall.end)
+        } else if let (Some(body), Some(shape)) = (ent.body(), ent.shape()) {
+            ray_circle(start, end, body.position(now), shape.radius)
+        } else {
+            None
+        };
+        if let Some(parameter) = hit {
+            if first.is_none_or(|(nearest, _)| parameter < nearest) {
+                first = Some((parameter, uid));
+            }
+        }
+    }
+    first.map(|(parameter, uid)| RayHit {
+        uid,
+        point: ray_point(start, end, parameter),
+        parameter,
+    })
+}
+
+fn dot(first: units::Vec2, second: units::Vec2) -> i128 {
+    first.x as i128 * second.x as i128 + first.y as i128 * second.y as i128
+}
+
+fn cross(first: units::Vec2, second: units::Vec2) -> i128 {
+    first.x as i128 * second.y as i128 - first.y as i128 * second.x as i128
+}
+
+fn length_sq(vec: units::Vec2) -> i128 {
+    dot(vec, vec)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: units::Time = 60 * 60 * units::SEC;
+
+    #[test]
+    fn fast_bodies_overflow() {
+        let velocity = units::Vec2 { x: units::Scalar::MAX / 2, y: 0 };
+        let body = Body::new(units::ZERO_VEC, velocity, 0);
+        let far = units::Vec2 { x: units::Scalar::MAX - 1, y: 0 };
+        assert_eq!(body.try_position(2), Ok(far));
+        assert_eq!(body.try_position(3), Err(units::Overflow));
+        assert_eq!(body.try_position(10), Err(units::Overflow));
+
+        // the plain version stops at the edge instead
+        let edge = units::Vec2 { x: units::Scalar::MAX, y: 0 };
+        assert_eq!(body.position(10), edge);
+
+        // going the other way too
+        let body = Body::new(units::ZERO_VEC, -velocity, 0);
+        assert_eq!(body.try_position(10), Err(units::Overflow));
+    }
+
+    #[test]
+    fn accelerating_bodies_overflow_as_documented() {
+        // units.rs says `a * h^2` has to stay below about 2 * 10^7
+        let acceleration = |a| units::Vec2 { x: 0, y: a };
+        let slower = Body::new_accelerating(
+            units::ZERO_VEC,
+            units::ZERO_VEC,
+            acceleration(20_000_000),
+            0
+        );
+        assert!(slower.try_position(HOUR).is_ok());
+        let faster = Body::new_accelerating(
+            units::ZERO_VEC,
+            units::ZERO_VEC,
+            acceleration(22_000_000),
+            0
+        );
+        assert_eq!(faster.try_position(HOUR), Err(units::Overflow));
+
+        // four times as long takes a sixteenth of the acceleration
+        let longer = Body::new_accelerating(
+            units::ZERO_VEC,
+            units::ZERO_VEC,
+            acceleration(22_000_000 / 16),
+            0
+        );
+        assert!(longer.try_position(HOUR).is_ok());
+        assert_eq!(longer.try_position(4 * HOUR), Err(units::Overflow));
+    }
+
+    #[test]
+    fn attached_bodies_overflow_when_turned_past_the_edge() {
+        let big = 3 << 61;
+        let mut parent = Body::new_frozen(units::ZERO_VEC);
+        parent.turn(units::TURN / 8, 0, 0);
+        let parent = anchor(parent);
+        let offset = units::Vec2 { x: big, y: big };
+        let body = Body::new_attached(&parent, offset, units::ZERO_VEC, 0);
+        assert_eq!(body.try_position(0), Err(units::Overflow));
+
+        // facing along an axis keeps the offset in range
+        parent.borrow_mut().turn(units::TURN / 4, 0, 0);
+        assert!(body.try_position(0).is_ok());
+    }
+
+    #[test]
+    fn splitting_at_the_same_velocity_keeps_the_motion() {
+        let velocity = units::Vec2 { x: 3, y: -7 };
+        for &a in &[10, -10, -1] {
+            let acceleration = units::Vec2 { x: a, y: 3 * a };
+            let original = Body::new_accelerating(
+                units::ZERO_VEC,
+                velocity,
+                acceleration,
+                0
+            );
+            let mut body = original.clone();
+            // splits at odd times, so that there is rounding to lose
+            let mut time = 0;
+            while time < 10 * units::SEC {
+                time += units::SEC / 3 + 17;
+                let now = body.velocity(time);
+                body.bounce(now, time);
+                assert_eq!(body.position(time), original.position(time));
+            }
+            let later = 20 * units::SEC;
+            assert_eq!(body.position(later), original.position(later));
+            assert_eq!(body.velocity(later), original.velocity(later));
+        }
+    }
+
+    #[test]
+    fn sliding_bodies_still_stop_where_they_were_going_to() {
+        let velocity = units::Vec2 { x: 300, y: -170 };
+        let distance = 200 * units::DOT;
+        let stop_time = stopping_time(velocity, distance);
+        let original = Body::new_sliding(
+            units::ZERO_VEC,
+            velocity,
+            stop_time,
+            0
+        );
+        let end = original.position(stop_time);
+        for &time in &[1, stop_time / 3, stop_time / 2 + 5, stop_time - 1] {
+            let mut body = original.clone();
+            body.bounce(original.velocity(time), time);
+            assert_eq!(body.halt_time(), Some(stop_time));
+            assert_eq!(body.position(stop_time + units::SEC), end);
+
+            // sliding again to the same halt, from what is left
+            let mut body = original.clone();
+            body.slide(stop_time - time, time);
+            let miss = body.position(stop_time) - end;
+            assert!(miss.x.abs() < units::DOT && miss.y.abs() < units::DOT);
+        }
+    }
+}
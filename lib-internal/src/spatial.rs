@@ -0,0 +1,315 @@
+use std::collections;
+
+use entity_heap;
+use physics;
+use units;
+
+type Cell = (i64, i64);
+
+// bodies whose bounding box covers more cells than this are kept aside
+//   and checked by every query instead of being filed cell by cell
+const MAX_CELLS: i128 = 1024;
+
+struct Entry {
+    body: physics::Body,
+    min: Cell,
+    max: Cell,
+}
+
+// a uniform grid of where bodies are during a window of time
+// each body is filed under every cell of the box bounding its path through
+//   the window, so a fast diagonal mover is filed under cells it never
+//   touches, and queries check the body's exact position at the time asked about,
+//   so queries are only complete for times within the window
+// bodies that bounce need to be inserted again to stay accurate
+pub struct SpatialIndex {
+    cell_size: units::Scalar,
+    from: units::Time,
+    until: units::Time,
+    cells: collections::HashMap<Cell, Vec<entity_heap::UID>>,
+    entries: collections::HashMap<entity_heap::UID, Entry>,
+    // bodies with too many cells to file, in order of UID
+    oversized: Vec<entity_heap::UID>,
+    // covers every cell anything has been filed under since the index
+    //   was last empty, so it can be larger than needed but never smaller
+    extent: Option<(Cell, Cell)>,
+}
+
+impl SpatialIndex {
+    pub fn new(
+        cell_size: units::Scalar,
+        from: units::Time,
+        until: units::Time,
+    ) -> SpatialIndex {
+        assert!(cell_size > 0, "SpatialIndex cells must have positive size");
+        SpatialIndex {
+            cell_size,
+            from,
+            until,
+            cells: collections::HashMap::new(),
+            entries: collections::HashMap::new(),
+            oversized: Vec::new(),
+            extent: None,
+        }
+    }
+
+    // indexes every entity that has a body
+    pub fn build(
+        space: &entity_heap::EntityHeap,
+        cell_size: units::Scalar,
+        from: units::Time,
+        until: units::Time,
+    ) -> SpatialIndex {
+        let mut index = SpatialIndex::new(cell_size, from, until);
+        for (&uid, ent) in space {
+            if let Some(body) = ent.body() {
+                index.insert(uid, body);
+            }
+        }
+        index
+    }
+
+    pub fn window(&self) -> (units::Time, units::Time) {
+        (self.from, self.until)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // adds a body, or replaces the body already indexed for this UID
+    pub fn insert(&mut self, uid: entity_heap::UID, body: &physics::Body) {
+        self.remove(uid);
+
+        let (low, high) = body.bounds(self.from, self.until);
+        let min = self.cell(low);
+        let max = self.cell(high);
+        if cell_count(min, max) > MAX_CELLS {
+            let at = self.oversized.binary_search(&uid).unwrap_or_else(|at| at);
+            self.oversized.insert(at, uid);
+        } else {
+            for x in min.0..max.0 + 1 {
+                for y in min.1..max.1 + 1 {
+                    self.cells
+                        .entry((x, y))
+                        .or_default()
+                        .push(uid);
+                }
+            }
+            self.extent = Some(match self.extent {
+                Some((low, high)) => (
+                    (low.0.min(min.0), low.1.min(min.1)),
+                    (high.0.max(max.0), high.1.max(max.1)),
+                ),
+                None => (min, max),
+            });
+        }
+
+        let body = body.clone();
+        self.entries.insert(uid, Entry { body, min, max });
+    }
+
+    pub fn remove(&mut self, uid: entity_heap::UID) -> bool {
+        let entry =
+            if let Some(entry) = self.entries.remove(&uid) {
+                entry
+            } else {
+                return false;
+            };
+        if self.entries.is_empty() {
+            self.extent = None;
+        }
+        if cell_count(entry.min, entry.max) > MAX_CELLS {
+            self.oversized.retain(|&other| other != uid);
+            return true;
+        }
+        for x in entry.min.0..entry.max.0 + 1 {
+            for y in entry.min.1..entry.max.1 + 1 {
+                let empty =
+                    if let Some(uids) = self.cells.get_mut(&(x, y)) {
+                        uids.retain(|&other| other != uid);
+                        uids.is_empty()
+                    } else {
+                        false
+                    };
+                if empty {
+                    self.cells.remove(&(x, y));
+                }
+            }
+        }
+        true
+    }
+
+    // entities whose position at `now` is no further than `radius`
+    //   from `center`, in order of UID
+    pub fn within_radius(
+        &self,
+        now: units::Time,
+        center: units::Position,
+        radius: units::Scalar,
+    ) -> Vec<entity_heap::UID> {
+        let reach = units::Vec2 { x: radius, y: radius };
+        let min = self.cell(center - reach);
+        let max = self.cell(center + reach);
+        let radius_sq = radius as i128 * radius as i128;
+        self.search(min, max, |position| {
+            distance_sq(position, center) <= radius_sq
+        }, now)
+    }
+
+    // entities whose position at `now` is inside the box with corners
+    //   `min` and `max`, edges included, in order of UID
+    pub fn within_rect(
+        &self,
+        now: units::Time,
+        min: units::Position,
+        max: units::Position,
+    ) -> Vec<entity_heap::UID> {
+        let min_cell = self.cell(min);
+        let max_cell = self.cell(max);
+        self.search(min_cell, max_cell, |position| {
+            min.x <= position.x && position.x <= max.x
+                && min.y <= position.y && position.y <= max.y
+        }, now)
+    }
+
+    // the entity closest to `point` at `now`, ties going to the lower UID
+    pub fn nearest(
+        &self,
+        now: units::Time,
+        point: units::Position,
+    ) -> Option<entity_heap::UID> {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        let mut best: Option<(i128, entity_heap::UID)> = None;
+        for &uid in &self.oversized {
+            let position = self.entries[&uid].body.position(now);
+            let candidate = (distance_sq(position, point), uid);
+            if best.is_none_or(|best| candidate < best) {
+                best = Some(candidate);
+            }
+        }
+
+        // no entity is filed further out than this many rings of cells
+        let center = self.cell(point);
+        let furthest = match self.extent {
+            Some((low, high)) => {
+                let dx = (center.0 - low.0).max(high.0 - center.0);
+                let dy = (center.1 - low.1).max(high.1 - center.1);
+                dx.max(dy).max(0)
+            },
+            None => -1,
+        };
+
+        let mut ring = 0;
+        while ring <= furthest {
+            for cell in ring_cells(center, ring) {
+                let uids = match self.cells.get(&cell) {
+                    Some(uids) => uids,
+                    None => continue,
+                };
+                for &uid in uids {
+                    let position = self.entries[&uid].body.position(now);
+                    let candidate = (distance_sq(position, point), uid);
+                    if best.is_none_or(|best| candidate < best) {
+                        best = Some(candidate);
+                    }
+                }
+            }
+
+            // anything in a later ring is at least `ring` whole cells away
+            if let Some((dist_sq, _)) = best {
+                let clear = ring as i128 * self.cell_size as i128;
+                if clear * clear >= dist_sq {
+                    break;
+                }
+            }
+            ring += 1;
+        }
+
+        best.map(|(_, uid)| uid)
+    }
+
+    fn cell(&self, position: units::Position) -> Cell {
+        (
+            position.x.div_euclid(self.cell_size),
+            position.y.div_euclid(self.cell_size),
+        )
+    }
+
+    fn search<F>(
+        &self,
+        min: Cell,
+        max: Cell,
+        accept: F,
+        now: units::Time,
+    ) -> Vec<entity_heap::UID>
+        where F: Fn(units::Position) -> bool
+    {
+        let width = (max.0 - min.0 + 1) as u64;
+        let height = (max.1 - min.1 + 1) as u64;
+        let mut found = Vec::new();
+        if width.saturating_mul(height) > self.cells.len() as u64 {
+            // cheaper to look at every occupied cell than every covered one
+            for (cell, uids) in &self.cells {
+                let inside = min.0 <= cell.0 && cell.0 <= max.0
+                    && min.1 <= cell.1 && cell.1 <= max.1;
+                if inside {
+                    found.extend(uids);
+                }
+            }
+        } else {
+            for x in min.0..max.0 + 1 {
+                for y in min.1..max.1 + 1 {
+                    if let Some(uids) = self.cells.get(&(x, y)) {
+                        found.extend(uids);
+                    }
+                }
+            }
+        }
+
+        found.extend(&self.oversized);
+        found.sort();
+        found.dedup();
+        found.retain(|uid| {
+            let position = self.entries[uid].body.position(now);
+            accept(position)
+        });
+        found
+    }
+}
+
+fn cell_count(min: Cell, max: Cell) -> i128 {
+    let width = max.0 as i128 - min.0 as i128 + 1;
+    let height = max.1 as i128 - min.1 as i128 + 1;
+    width * height
+}
+
+fn distance_sq(a: units::Position, b: units::Position) -> i128 {
+    let dx = a.x as i128 - b.x as i128;
+    let dy = a.y as i128 - b.y as i128;
+    dx * dx + dy * dy
+}
+
+// the cells exactly `ring` cells away from `center`, counting diagonals as one
+fn ring_cells(center: Cell, ring: i64) -> Vec<Cell> {
+    if ring == 0 {
+        return vec![center];
+    }
+    let mut cells = Vec::new();
+    for offset in -ring..ring + 1 {
+        cells.push((center.0 + offset, center.1 - ring));
+        cells.push((center.0 + offset, center.1 + ring));
+    }
+    for offset in -ring + 1..ring {
+        cells.push((center.0 - ring, center.1 + offset));
+        cells.push((center.0 + ring, center.1 + offset));
+    }
+    cells
+}
@@ -1,9 +1,13 @@
+use std::error;
+use std::f64;
+use std::fmt;
 use std::ops;
 
 
 pub type Time = i64;
 pub type Duration = i64;
 pub type Scalar = i64;
+pub type Angle = i64;
 
 pub const SEC: Time = 1 << 16; // 65536
 // meant to be a minimal unit of time for rendering
@@ -15,8 +19,54 @@ pub const MOMENT: Time = SEC / 16;
 // it represents something close to the minimum reasonable distance
 // when working with velocities
 pub const DOT: Scalar = SEC;
+// a whole revolution, going from the x axis towards the y axis
+// like a DOT, it is chosen so that turning at a rate of 1 per tick
+//   makes one TURN in a SEC
+pub const TURN: Angle = SEC;
 
-#[derive(Clone, Copy, Debug)]
+// LIMITS
+// a Time counts ticks from the start of the game, and runs out after
+//   2^63 ticks, which is 2^47 SECs or about four million years
+// a Scalar holds positions up to 2^47 DOTs either side of the origin,
+//   which is the real limit, since positions are worked out by multiplying
+//   velocities by durations
+// to stay in range for a game lasting `h` hours:
+//   a velocity of `v` has to keep `v * h` below about 3.9 * 10^10,
+//   since it moves `v` DOTs every SEC, and
+//   an acceleration of `a` has to keep `a * h^2` below about 2 * 10^7
+// so anything sensible is fine for hours, and only bodies left
+//   accelerating for days, or moving at absurd speeds, run out of room
+// Body::try_position reports when that happens, and the checked
+//   and saturating operations below stay in range,
+//   while the plain operators panic in debug builds and wrap in release
+
+// a value that has gone beyond the limits of the units
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Overflow;
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "value is too large for the fixed point units")
+    }
+}
+
+impl error::Error for Overflow {
+}
+
+// for narrowing the wider intermediate values that calculations use
+pub fn checked_scalar(wide: i128) -> Result<Scalar, Overflow> {
+    if wide < Scalar::MIN as i128 || wide > Scalar::MAX as i128 {
+        Err(Overflow)
+    } else {
+        Ok(wide as Scalar)
+    }
+}
+
+pub fn saturating_scalar(wide: i128) -> Scalar {
+    wide.max(Scalar::MIN as i128).min(Scalar::MAX as i128) as Scalar
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Vec2 {
     pub x: Scalar,
     pub y: Scalar,
@@ -28,6 +78,98 @@ pub const ZERO_VEC: Vec2 = Vec2 {
     y: 0,
 };
 
+pub fn radians(angle: Angle) -> f64 {
+    angle as f64 / TURN as f64 * 2.0 * f64::consts::PI
+}
+
+impl Vec2 {
+    // rounds to the nearest unit
+    pub fn rotate(self, angle: Angle) -> Vec2 {
+        let (x, y) = self.rotate_wide(angle);
+        Vec2 {
+            x: x as Scalar,
+            y: y as Scalar,
+        }
+    }
+
+    // a vector near the limits can turn to point past them
+    pub fn checked_rotate(self, angle: Angle) -> Option<Vec2> {
+        let (x, y) = self.rotate_wide(angle);
+        // MAX as f64 rounds up to 2^63, which is already too far
+        let limit = Scalar::MAX as f64;
+        if x.abs() >= limit || y.abs() >= limit {
+            return None;
+        }
+        Some(Vec2 {
+            x: x as Scalar,
+            y: y as Scalar,
+        })
+    }
+
+    fn rotate_wide(self, angle: Angle) -> (f64, f64) {
+        let (sin, cos) = radians(angle).sin_cos();
+        let x = self.x as f64;
+        let y = self.y as f64;
+        ((x * cos - y * sin).round(), (x * sin + y * cos).round())
+    }
+
+    pub fn checked_add(self, other: Vec2) -> Option<Vec2> {
+        Some(Vec2 {
+            x: self.x.checked_add(other.x)?,
+            y: self.y.checked_add(other.y)?,
+        })
+    }
+
+    pub fn checked_sub(self, other: Vec2) -> Option<Vec2> {
+        Some(Vec2 {
+            x: self.x.checked_sub(other.x)?,
+            y: self.y.checked_sub(other.y)?,
+        })
+    }
+
+    pub fn checked_mul(self, scale: Scalar) -> Option<Vec2> {
+        Some(Vec2 {
+            x: self.x.checked_mul(scale)?,
+            y: self.y.checked_mul(scale)?,
+        })
+    }
+
+    pub fn checked_div(self, scale: Scalar) -> Option<Vec2> {
+        Some(Vec2 {
+            x: self.x.checked_div(scale)?,
+            y: self.y.checked_div(scale)?,
+        })
+    }
+
+    pub fn checked_neg(self) -> Option<Vec2> {
+        Some(Vec2 {
+            x: self.x.checked_neg()?,
+            y: self.y.checked_neg()?,
+        })
+    }
+
+    pub fn saturating_add(self, other: Vec2) -> Vec2 {
+        Vec2 {
+            x: self.x.saturating_add(other.x),
+            y: self.y.saturating_add(other.y),
+        }
+    }
+
+    pub fn saturating_sub(self, other: Vec2) -> Vec2 {
+        Vec2 {
+            x: self.x.saturating_sub(other.x),
+            y: self.y.saturating_sub(other.y),
+        }
+    }
+
+    pub fn saturating_mul(self, scale: Scalar) -> Vec2 {
+        Vec2 {
+            x: self.x.saturating_mul(scale),
+            y: self.y.saturating_mul(scale),
+        }
+    }
+}
+
 impl ops::AddAssign<Vec2> for Vec2 {
     fn add_assign(&mut self, rhs: Vec2) {
         self.x += rhs.x;
@@ -114,3 +256,85 @@ pub type Displacement = Vec2;
 pub type Velocity = Vec2;
 
 
+// velocity gained per SEC, rather than per tick,
+//   so that modest accelerations don't need fractional units
+pub type Acceleration = Vec2;
+// angle turned per tick
+pub type AngularVelocity = Angle;
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_VEC: Vec2 = Vec2 { x: Scalar::MAX, y: Scalar::MAX };
+    const MIN_VEC: Vec2 = Vec2 { x: Scalar::MIN, y: Scalar::MIN };
+
+    #[test]
+    fn narrowing_at_the_limits() {
+        let max = Scalar::MAX as i128;
+        let min = Scalar::MIN as i128;
+        assert_eq!(checked_scalar(max), Ok(Scalar::MAX));
+        assert_eq!(checked_scalar(min), Ok(Scalar::MIN));
+        assert_eq!(checked_scalar(max + 1), Err(Overflow));
+        assert_eq!(checked_scalar(min - 1), Err(Overflow));
+
+        assert_eq!(saturating_scalar(max + 1), Scalar::MAX);
+        assert_eq!(saturating_scalar(min - 1), Scalar::MIN);
+        assert_eq!(saturating_scalar(-5), -5);
+    }
+
+    #[test]
+    fn checked_ops_at_the_limits() {
+        let one = Vec2 { x: 1, y: 1 };
+        assert_eq!(MAX_VEC.checked_add(one), None);
+        assert_eq!(MAX_VEC.checked_add(-one), Some(MAX_VEC - one));
+        assert_eq!(MIN_VEC.checked_sub(one), None);
+        assert_eq!(MIN_VEC.checked_sub(-one), Some(MIN_VEC + one));
+        assert_eq!(MAX_VEC.checked_mul(2), None);
+        assert_eq!(MIN_VEC.checked_mul(-1), None);
+        assert_eq!(MAX_VEC.checked_mul(-1), Some(-MAX_VEC));
+        assert_eq!(MIN_VEC.checked_div(-1), None);
+        assert_eq!(one.checked_div(0), None);
+        assert_eq!(MIN_VEC.checked_neg(), None);
+        assert_eq!(MAX_VEC.checked_neg(), Some(MIN_VEC + one));
+
+        // only one axis has to go out of range
+        let edge = Vec2 { x: 0, y: Scalar::MAX };
+        assert_eq!(edge.checked_add(one), None);
+    }
+
+    #[test]
+    fn saturating_ops_at_the_limits() {
+        let one = Vec2 { x: 1, y: -1 };
+        let clamped = Vec2 { x: Scalar::MAX, y: Scalar::MAX - 1 };
+        assert_eq!(MAX_VEC.saturating_add(one), clamped);
+        let clamped = Vec2 { x: Scalar::MIN, y: Scalar::MIN + 1 };
+        assert_eq!(MIN_VEC.saturating_sub(one), clamped);
+        assert_eq!(MAX_VEC.saturating_mul(2), MAX_VEC);
+        assert_eq!(MAX_VEC.saturating_mul(-2), MIN_VEC);
+    }
+
+    #[test]
+    fn checked_rotate_near_the_limits() {
+        // rounding in sin and cos puts huge vectors a few hundred units off
+        let near = |first: Scalar, second: Scalar| {
+            (first as f64 - second as f64).abs() < 1e4
+        };
+
+        let big = 3 << 61;
+        let diagonal = Vec2 { x: big, y: big };
+        // pointing along either axis it is sqrt(2) times longer than it was
+        assert_eq!(diagonal.checked_rotate(TURN / 8), None);
+        assert_eq!(diagonal.checked_rotate(-TURN / 8), None);
+        let turned = diagonal.checked_rotate(TURN / 4).unwrap();
+        assert!(near(turned.x, -big) && near(turned.y, big));
+
+        // MAX itself is already too far once it is in floating point
+        let edge = Vec2 { x: Scalar::MAX, y: 0 };
+        assert_eq!(edge.checked_rotate(0), None);
+        let inside = Vec2 { x: 1 << 62, y: 0 };
+        let turned = inside.checked_rotate(TURN / 2).unwrap();
+        assert!(near(turned.x, -(1 << 62)) && near(turned.y, 0));
+    }
+}
@@ -0,0 +1,216 @@
+use std::cell;
+use std::rc;
+
+use entity_heap;
+use event_queue;
+use physics;
+use units;
+
+// a fixed line segment that moving bodies bounce off
+pub struct Wall {
+    pub start: units::Position,
+    pub end: units::Position,
+}
+
+impl entity_heap::AsEntity for Wall {
+    const KIND: &'static str = "Wall";
+}
+
+impl Wall {
+    pub fn new(start: units::Position, end: units::Position) -> Wall {
+        Wall { start, end }
+    }
+
+    // the four sides of an axis aligned box
+    pub fn rectangle(min: units::Position, max: units::Position) -> Vec<Wall> {
+        let top_right = units::Vec2 { x: max.x, y: min.y };
+        let bottom_left = units::Vec2 { x: min.x, y: max.y };
+        vec![
+            Wall::new(min, top_right),
+            Wall::new(top_right, max),
+            Wall::new(max, bottom_left),
+            Wall::new(bottom_left, min),
+        ]
+    }
+}
+
+// where the owner of an entity can find its next WallBounceEvent,
+//   or WallRecheckEvent, which is a new one every time it bounces
+//   or looks again, in order to cancel it
+pub type BounceTracker = rc::Rc<cell::Cell<Option<event_queue::EventID>>>;
+
+// the moment an entity is predicted to reach a wall
+// like a CollisionEvent, this does nothing if the entity has bounced since,
+//   or been carried off by an anchor that has moved, or if the wall has gone
+pub struct WallBounceEvent {
+    target: entity_heap::UID,
+    wall: entity_heap::EntityRef<Wall>,
+    body: physics::Body,
+    tracker: Option<BounceTracker>,
+}
+
+impl event_queue::Event for WallBounceEvent {
+    fn invoke(
+        self: Self,
+        space: &mut entity_heap::EntityHeap,
+        time: &mut event_queue::EventQueue
+    ) -> Result<(), event_queue::EventError> {
+        let now = time.now();
+        let velocity = match space.get(self.wall) {
+            Some(wall) => {
+                let position = self.body.position(now);
+                let normal =
+                    physics::wall_normal(position, wall.start, wall.end);
+                physics::reflect(self.body.velocity(now), normal)
+            },
+            None => return Ok(()),
+        };
+        let body = match space
+            .entity_mut(self.target)
+            .and_then(entity_heap::Entity::body_mut)
+        {
+            Some(body) => body,
+            None => return Ok(()),
+        };
+        if *body != self.body {
+            return Ok(());
+        }
+        body.bounce(velocity, now);
+        repredict(space, time, self.target, self.tracker)?;
+        Ok(())
+    }
+
+    fn kind(&self) -> &'static str {
+        "WallBounceEvent"
+    }
+
+    fn describe(&self) -> String {
+        format!("bounce {} off wall {}", self.target, self.wall.uid())
+    }
+
+    fn entities(&self) -> Vec<entity_heap::UID> {
+        vec![self.target, self.wall.uid()]
+    }
+}
+
+// as far as predict could look ahead without the entity reaching a wall
+// if it hasn't bounced by then, this carries on looking from here
+pub struct WallRecheckEvent {
+    target: entity_heap::UID,
+    body: physics::Body,
+    tracker: Option<BounceTracker>,
+}
+
+impl event_queue::Event for WallRecheckEvent {
+    fn invoke(
+        self: Self,
+        space: &mut entity_heap::EntityHeap,
+        time: &mut event_queue::EventQueue
+    ) -> Result<(), event_queue::EventError> {
+        let unchanged = space
+            .entity(self.target)
+            .and_then(entity_heap::Entity::body)
+            .is_some_and(|body| *body == self.body);
+        if !unchanged {
+            return Ok(());
+        }
+        repredict(space, time, self.target, self.tracker)?;
+        Ok(())
+    }
+
+    fn kind(&self) -> &'static str {
+        "WallRecheckEvent"
+    }
+
+    fn describe(&self) -> String {
+        format!("look again at walls for {}", self.target)
+    }
+
+    fn entities(&self) -> Vec<entity_heap::UID> {
+        vec![self.target]
+    }
+}
+
+// schedules a WallBounceEvent for the first wall the entity will reach,
+//   treating it as a circle the size of its shape
+// if that is further off than wall_contact_time looks, this schedules
+//   a WallRecheckEvent instead, and gives that
+// gives None if it never will, or if it has no body or shape
+// this needs calling again whenever something else bounces the entity
+pub fn predict(
+    space: &entity_heap::EntityHeap,
+    time: &mut event_queue::EventQueue,
+    target: entity_heap::UID,
+) -> Result<Option<event_queue::EventID>, event_queue::EventError> {
+    schedule(space, time, target, None)
+}
+
+// the same, and keeps `tracker` up to date with the next bounce
+//   from now on, including the ones after this
+pub fn predict_tracked(
+    space: &entity_heap::EntityHeap,
+    time: &mut event_queue::EventQueue,
+    target: entity_heap::UID,
+    tracker: BounceTracker,
+) -> Result<Option<event_queue::EventID>, event_queue::EventError> {
+    let next = schedule(space, time, target, Some(rc::Rc::clone(&tracker)));
+    tracker.set(next.unwrap_or(None));
+    next
+}
+
+// predicts again the same way as before
+fn repredict(
+    space: &entity_heap::EntityHeap,
+    time: &mut event_queue::EventQueue,
+    target: entity_heap::UID,
+    tracker: Option<BounceTracker>,
+) -> Result<Option<event_queue::EventID>, event_queue::EventError> {
+    match tracker {
+        Some(tracker) => predict_tracked(space, time, target, tracker),
+        None => predict(space, time, target),
+    }
+}
+
+fn schedule(
+    space: &entity_heap::EntityHeap,
+    time: &mut event_queue::EventQueue,
+    target: entity_heap::UID,
+    tracker: Option<BounceTracker>,
+) -> Result<Option<event_queue::EventID>, event_queue::EventError> {
+    let ent = space
+        .entity(target)
+        .ok_or(entity_heap::EntityError::MissingEntity(target))?;
+    let (body, radius) = match (ent.body(), ent.shape()) {
+        (Some(body), Some(shape)) => (body, shape.radius),
+        _ => return Ok(None),
+    };
+
+    let now = time.now();
+    let first = space
+        .of_kind::<Wall>()
+        .map(|(wall, matter)| {
+            let contact = physics::wall_contact_time(
+                body,
+                radius,
+                matter.start,
+                matter.end,
+                now
+            );
+            (contact, wall)
+        })
+        .min_by_key(|&(contact, _)| contact);
+
+    let body = body.snapshot();
+    let id = match first {
+        Some((physics::Contact::At(contact), wall)) => {
+            let event = WallBounceEvent { target, wall, body, tracker };
+            time.enqueue_at(event, contact)?
+        },
+        Some((physics::Contact::RecheckAt(recheck), _)) => {
+            let event = WallRecheckEvent { target, body, tracker };
+            time.enqueue_at(event, recheck)?
+        },
+        Some((physics::Contact::Never, _)) | None => return Ok(None),
+    };
+    Ok(Some(id))
+}
@@ -1,5 +1,6 @@
 use charm_internal::forms::effects;
 use charm_internal::units;
+use charm_internal::walls;
 
 use piston_window as app;
 
@@ -21,6 +22,17 @@ pub fn draw_at<G: Draw>(
     draw.draw(trans, graphics);
 }
 
+pub fn draw_wall(
+    wall: &walls::Wall,
+    center: app::math::Matrix2d,
+    graphics: &mut app::G2d
+) {
+    let start = floatify_position(wall.start);
+    let end = floatify_position(wall.end);
+    let line = [start[0], start[1], end[0], end[1]];
+    app::line([0.5, 0.5, 0.5, 1.0], 1.0, line, center, graphics);
+}
+
 fn floatify_position(position: units::Position) -> [f64; 2] {
     let units::Vec2 {x, y} = position;
     let x = x as f64 / units::DOT as f64;
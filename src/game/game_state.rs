@@ -5,9 +5,18 @@ use charm_internal::entity_heap;
 use charm_internal::event_queue;
 use charm_internal::physics;
 use charm_internal::units;
+use charm_internal::walls;
 
 use game::user_input;
 
+// the most events that a single frame may run
+// when a spell exceeds this the game clock stutters instead of freezing,
+//   since the clock is never allowed to get far ahead of the simulation
+const EVENT_BUDGET: usize = 10_000;
+
+// half the width of the square arena, which just fits in the window
+const ARENA_RADIUS: units::Scalar = 290 * units::DOT;
+
 struct PlayerEffect;
 
 impl effects::Effect for PlayerEffect {
@@ -20,6 +29,7 @@ impl effects::Effect for PlayerEffect {
 pub struct Player {
     pub shape: effects::Circle,
     pub body: physics::Body,
+    pub caster: entity_heap::CasterID,
     speed: units::Scalar,
 }
 
@@ -31,9 +41,12 @@ impl Player {
 
         let body = physics::Body::new_frozen(units::ZERO_VEC);
 
+        // there is only one player for now
+        let caster = 0;
+
         let speed = 100;
 
-        Player { shape, body, speed }
+        Player { shape, body, caster, speed }
     }
 }
 
@@ -46,29 +59,35 @@ pub struct GameState {
 
 impl GameState {
     pub fn new() -> GameState {
-        let space = entity_heap::EntityHeap::new();
+        let mut space = entity_heap::EntityHeap::new();
         let time = event_queue::EventQueue::new();
         let player = Player::new();
 
+        let corner = units::Vec2 { x: ARENA_RADIUS, y: ARENA_RADIUS };
+        for wall in walls::Wall::rectangle(-corner, corner) {
+            entity_heap::new_entity(&mut space, wall);
+        }
+
         GameState { time, space, player }
     }
 
-    pub fn simulate(&mut self, until: units::Time) {
-        self.time.simulate(&mut self.space, until);
+    pub fn simulate(&mut self, until: units::Time) -> event_queue::Report {
+        self.time.simulate_bounded(&mut self.space, until, EVENT_BUDGET)
     }
 
     pub fn cast_as_player(
         &mut self,
         action: rc::Rc<effects::Cast>,
         target: units::Position
-    ) {
+    ) -> Result<(), event_queue::EventError> {
         effects::Cast::cast(
             &*action,
             &mut self.space,
             &mut self.time,
             self.player.body.clone(),
             target,
-        );
+            entity_heap::Lineage::cast_by(self.player.caster),
+        )
     }
 
     // TODO make DeviceAction enum
@@ -1,4 +1,5 @@
 use charm_internal::units;
+use charm_internal::walls;
 
 use piston_window as app;
 
@@ -32,7 +33,10 @@ impl Game {
 
     pub fn on_update(&mut self, _upd: app::UpdateArgs) {
         let now = self.real_time.time();
-        self.state.simulate(now);
+        let report = self.state.simulate(now);
+        for failure in report.failures {
+            eprintln!("{}", failure);
+        }
     }
 
     pub fn on_input(&mut self, bin: app::ButtonArgs) {
@@ -43,7 +47,9 @@ impl Game {
             Nop => (),
             Cast { target } => {
                 let action = self.arsenal.current();
-                self.state.cast_as_player(action, target);
+                if let Err(err) = self.state.cast_as_player(action, target) {
+                    eprintln!("failed to cast: {}", err);
+                }
             },
             ChangeMovement { dirs } => {
                 self.state.update_movement(dirs);
@@ -87,21 +93,13 @@ impl Game {
         let position = self.state.player.body.position(now);
         draw::draw_at(&self.state.player.shape, position, center, graphics);
 
-        for (&_uid, ent) in &self.state.space {
-            // TODO make generic functions for rendering things
-            // really the objects should generate a Graphics enum
-            // and then Draw should be implemented for the enum itself
-            use charm_internal::entity_heap::Entity::{Smoke, Bolt};
-            match *ent {
-                Smoke(ref item) => {
-                    let position = item.body.position(now);
-                    draw::draw_at(&item.shape, position, center, graphics);
-                },
-                Bolt(ref item) => {
-                    let position = item.body.position(now);
-                    draw::draw_at(&item.shape, position, center, graphics);
-                },
-            }
+        for (_uid, body, shape) in self.state.space.bodies_and_shapes() {
+            let position = body.position(now);
+            draw::draw_at(shape, position, center, graphics);
+        }
+
+        for (_uid, wall) in self.state.space.of_kind::<walls::Wall>() {
+            draw::draw_wall(wall, center, graphics);
         }
     }
 }